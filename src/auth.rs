@@ -0,0 +1,318 @@
+use crate::file_management::{STATE_DIR_NAME, is_excluded_folder_name};
+use crate::journal::ReviewOrigins;
+use anyhow::{Context, Result, bail};
+use async_graphql::Enum;
+use axum::extract::{Request, State};
+use axum::http::{StatusCode, Uri, header};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use percent_encoding::percent_decode_str;
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::path::{Component, Path};
+use std::sync::Arc;
+use std::{env, fs};
+use tracing::{info, warn};
+
+/// The query parameter with the token, for requests that cannot set headers like `<img>` tags
+pub const TOKEN_QUERY_PARAMETER: &str = "access_token";
+
+/// What a user may do, each role may do everything that the roles before it may do
+#[derive(Debug, Enum, Copy, Clone, Default, Eq, PartialEq, Ord, PartialOrd, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// Browse the photos
+    #[default]
+    Viewer,
+    /// Review, rate and defer photos, and undo their own reviews
+    Reviewer,
+    /// Undo the reviews of others
+    Admin,
+}
+
+/// Who sent a request. Without authentication every request is from the anonymous user, who is
+/// an admin.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct User {
+    pub name: String,
+    pub role: Role,
+    /// The folders relative to the media root that the user has access to, all folders when
+    /// `None`
+    pub folders: Option<Vec<String>>,
+}
+
+impl User {
+    #[must_use]
+    pub fn anonymous() -> Self {
+        Self {
+            name: "anonymous".into(),
+            role: Role::Admin,
+            folders: None,
+        }
+    }
+
+    pub fn require(&self, role: Role) -> Result<()> {
+        if self.role < role {
+            bail!("{} needs the {:?} role for this", self.name, role);
+        }
+        Ok(())
+    }
+
+    /// Whether the user has access to the file or folder at the path relative to the media root.
+    /// Users with access to a folder also have access to the photos of that folder that were
+    /// reviewed. These are in the album with the same name in the review buckets, so the folder
+    /// that they were reviewed from is looked up in the journal, photos that the journal does
+    /// not know are matched by the name of their album. Nobody has access to the state of
+    /// photomanager, like the journal and the preview cache.
+    pub fn can_access(&self, relative_path: &Path, origins: &ReviewOrigins) -> bool {
+        if relative_path
+            .components()
+            .any(|c| !matches!(c, Component::Normal(_)))
+        {
+            return false;
+        }
+        let mut components = relative_path.components().map(|c| c.as_os_str());
+        let first = components.next().and_then(|first| first.to_str());
+        if first == Some(STATE_DIR_NAME) {
+            return false;
+        }
+        let Some(folders) = &self.folders else {
+            return true;
+        };
+        let has_folder = |path: &Path| {
+            folders
+                .iter()
+                .any(|folder| path.starts_with(folder.trim_matches('/')))
+        };
+        match first {
+            Some(bucket) if is_excluded_folder_name(bucket) => {
+                match origins.folder(relative_path) {
+                    Some(origin) => has_folder(&origin),
+                    None => components.next().is_some_and(|album| {
+                        folders
+                            .iter()
+                            .any(|folder| Path::new(folder).file_name() == Some(album))
+                    }),
+                }
+            }
+            _ => has_folder(relative_path),
+        }
+    }
+
+    /// Fails unless the user has the role and access to the path relative to the media root
+    pub fn authorize(
+        &self,
+        role: Role,
+        relative_path: &str,
+        origins: &ReviewOrigins,
+    ) -> Result<()> {
+        self.require(role)?;
+        if !self.can_access(Path::new(relative_path.trim_start_matches('/')), origins) {
+            bail!("{} has no access to {}", self.name, relative_path);
+        }
+        Ok(())
+    }
+}
+
+#[derive(Deserialize)]
+struct UserConfig {
+    name: String,
+    token: String,
+    #[serde(default)]
+    role: Role,
+    folders: Option<Vec<String>>,
+}
+
+#[derive(Deserialize)]
+struct AuthConfig {
+    users: Vec<UserConfig>,
+}
+
+/// Validates the bearer tokens of the users in the toml file at `AUTH_CONFIG`, e.g.
+///
+/// ```toml
+/// [[users]]
+/// name = "alice"
+/// token = "a-long-random-token"
+/// role = "admin"
+///
+/// [[users]]
+/// name = "kid"
+/// token = "another-long-random-token"
+/// role = "reviewer"
+/// folders = ["phones/kid"]
+/// ```
+///
+/// Without `AUTH_CONFIG` authentication is disabled.
+pub struct Authenticator {
+    // tokens are compared by hash, which takes the same time whatever the token is
+    users: Option<Vec<(blake3::Hash, User)>>,
+}
+
+impl Authenticator {
+    pub fn from_env() -> Result<Self> {
+        match env::var("AUTH_CONFIG") {
+            Ok(config_path) => {
+                let config = fs::read_to_string(&config_path)
+                    .with_context(|| format!("Failed to read '{config_path}'"))?;
+                let authenticator = Self::from_config(&config)
+                    .with_context(|| format!("Failed to load users from '{config_path}'"))?;
+                info!("Authentication is enabled");
+                Ok(authenticator)
+            }
+            Err(_) => {
+                warn!(
+                    "Authentication is disabled, anyone who can reach the server can review all photos. Set AUTH_CONFIG to enable it."
+                );
+                Ok(Self::disabled())
+            }
+        }
+    }
+
+    /// Parses the users from a toml configuration
+    pub fn from_config(config: &str) -> Result<Self> {
+        let config = toml::from_str::<AuthConfig>(config)?;
+        if config.users.is_empty() {
+            bail!("at least one user is required");
+        }
+        let mut names = HashSet::new();
+        let mut tokens = HashSet::new();
+        let mut users = vec![];
+        for user in config.users {
+            if !names.insert(user.name.clone()) {
+                bail!("duplicate user name '{}'", user.name);
+            }
+            // tokens are passed in urls as they are, so they are limited to unreserved characters
+            let is_url_safe = user
+                .token
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "-._~".contains(c));
+            if user.token.len() < 16 || !is_url_safe || !tokens.insert(user.token.clone()) {
+                bail!(
+                    "the token of '{}' must be unique, at least 16 characters and only contain letters, digits and -._~",
+                    user.name
+                );
+            }
+            users.push((
+                blake3::hash(user.token.as_bytes()),
+                User {
+                    name: user.name,
+                    role: user.role,
+                    folders: user.folders,
+                },
+            ));
+        }
+        Ok(Self { users: Some(users) })
+    }
+
+    #[must_use]
+    pub fn disabled() -> Self {
+        Self { users: None }
+    }
+
+    pub const fn is_enabled(&self) -> bool {
+        self.users.is_some()
+    }
+
+    /// The user with the token, or the anonymous user when authentication is disabled
+    pub fn authenticate(&self, token: Option<&str>) -> Option<User> {
+        let Some(users) = &self.users else {
+            return Some(User::anonymous());
+        };
+        let hash = blake3::hash(token?.as_bytes());
+        users
+            .iter()
+            .find(|(user_hash, _)| *user_hash == hash)
+            .map(|(_, user)| user.clone())
+    }
+
+    /// The user of the `connection_init` payload of a GraphQL websocket, which holds the token as
+    /// `token` or as an `Authorization` bearer value
+    pub fn authenticate_connection(&self, payload: &serde_json::Value) -> Option<User> {
+        let token = payload
+            .get("token")
+            .and_then(serde_json::Value::as_str)
+            .or_else(|| {
+                ["Authorization", "authorization"]
+                    .iter()
+                    .find_map(|key| payload.get(key)?.as_str())
+                    .and_then(|value| value.strip_prefix("Bearer "))
+            });
+        self.authenticate(token)
+    }
+}
+
+/// Middleware that rejects requests without a valid token with 401 and adds the `User` of the
+/// token to the request extensions. The token is taken from the `Authorization: Bearer` header
+/// or from the `access_token` query parameter.
+pub async fn require_auth(
+    State(authenticator): State<Arc<Authenticator>>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    match authenticator.authenticate(request_token(&request)) {
+        Some(user) => {
+            request.extensions_mut().insert(user);
+            next.run(request).await
+        }
+        None => (
+            StatusCode::UNAUTHORIZED,
+            [(header::WWW_AUTHENTICATE, "Bearer")],
+            "Unauthorized",
+        )
+            .into_response(),
+    }
+}
+
+/// Middleware for services under the media root, like `/media`, that rejects requests for files
+/// that the user of the request has no access to with 403. It runs after `require_auth`, inside
+/// the service so that the path is relative to the media root.
+pub async fn require_folder_access(
+    State(origins): State<Arc<ReviewOrigins>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let relative_path = percent_decode_str(request.uri().path().trim_start_matches('/'))
+        .decode_utf8_lossy()
+        .into_owned();
+    let has_access = request
+        .extensions()
+        .get::<User>()
+        .is_some_and(|user| user.can_access(Path::new(&relative_path), &origins));
+    if has_access {
+        next.run(request).await
+    } else {
+        (StatusCode::FORBIDDEN, "Forbidden").into_response()
+    }
+}
+
+fn request_token(request: &Request) -> Option<&str> {
+    request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .or_else(|| {
+            request
+                .uri()
+                .query()?
+                .split('&')
+                .find_map(|pair| pair.strip_prefix(TOKEN_QUERY_PARAMETER)?.strip_prefix('='))
+        })
+}
+
+/// The uri with the value of the token query parameter replaced, so that tokens are not logged
+pub fn redact_token(uri: &Uri) -> String {
+    let Some(query) = uri.query() else {
+        return uri.to_string();
+    };
+    let query = query
+        .split('&')
+        .map(|pair| match pair.strip_prefix(TOKEN_QUERY_PARAMETER) {
+            Some(value) if value.starts_with('=') => format!("{TOKEN_QUERY_PARAMETER}=redacted"),
+            _ => pair.into(),
+        })
+        .collect::<Vec<_>>()
+        .join("&");
+    format!("{}?{query}", uri.path())
+}
@@ -0,0 +1,73 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::error;
+
+const DEFERRED_FILE_NAME: &str = "deferred.json";
+
+/// Photos that the reviewer is not sure about yet. They are left where they are, but skipped by
+/// the review queue until they are reviewed or undeferred.
+#[derive(Default, Serialize, Deserialize)]
+pub struct DeferredPhotos {
+    #[serde(skip)]
+    path: PathBuf,
+    /// When each photo was deferred, by path relative to the media root
+    photos: BTreeMap<String, DateTime<Utc>>,
+}
+
+impl DeferredPhotos {
+    pub fn load(state_dir: &Path) -> Self {
+        let path = state_dir.join(DEFERRED_FILE_NAME);
+        let mut deferred = Self::read(&path).unwrap_or_else(|e| {
+            error!("Failed to load the deferred photos, starting without deferred photos: {e:#}");
+            Self::default()
+        });
+        deferred.path = path;
+        deferred
+    }
+
+    fn read(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        serde_json::from_slice::<Self>(&fs::read(path)?)
+            .with_context(|| format!("Failed to parse deferred photos '{}'", path.display()))
+    }
+
+    pub fn defer(&mut self, relative_path: &str) {
+        self.photos.insert(relative_path.into(), Utc::now());
+    }
+
+    /// Returns whether the photo was deferred
+    pub fn undefer(&mut self, relative_path: &str) -> bool {
+        self.photos.remove(relative_path).is_some()
+    }
+
+    pub fn contains(&self, relative_path: &str) -> bool {
+        self.photos.contains_key(relative_path)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &DateTime<Utc>)> {
+        self.photos.iter()
+    }
+
+    // photos that have been moved or deleted since they were deferred are forgotten
+    pub fn save(&mut self, root_dir: &str) {
+        self.photos
+            .retain(|relative_path, _| Path::new(root_dir).join(relative_path).exists());
+        if let Err(e) = self.write() {
+            error!("Failed to save the deferred photos: {e:#}");
+        }
+    }
+
+    fn write(&self) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&self.path, serde_json::to_vec(self)?)
+            .with_context(|| format!("Failed to write deferred photos '{}'", self.path.display()))
+    }
+}
@@ -0,0 +1,94 @@
+use crate::reviewscore::ReviewScore;
+use async_graphql::futures_util::{Stream, stream};
+use async_graphql::{Enum, SimpleObject};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tracing::error;
+
+// events that are published while a slow subscriber lags behind by more are dropped for it
+const EVENT_CAPACITY: usize = 256;
+
+/// A photo was reviewed, by any client. Urls are those of `/media`.
+#[derive(Debug, Clone, SimpleObject)]
+pub struct PhotoReviewed {
+    /// Url of the photo before the review
+    pub path: String,
+    /// Url of the photo in its bucket
+    pub destination: String,
+    pub score: ReviewScore,
+}
+
+/// Photos were added to a folder, like by a sync app or by undoing reviews
+#[derive(Debug, Clone, SimpleObject)]
+pub struct NewPhotosArrived {
+    /// Path of the folder relative to the media root
+    pub folder: String,
+    pub count: usize,
+}
+
+#[derive(Debug, Enum, Copy, Clone, Eq, PartialEq)]
+pub enum UploadStatus {
+    Uploading,
+    Uploaded,
+    Failed,
+}
+
+#[derive(Debug, Clone, SimpleObject)]
+pub struct UploadStatusChanged {
+    /// Url of the reviewed photo
+    pub path: String,
+    pub status: UploadStatus,
+    /// Why the upload failed
+    pub message: Option<String>,
+}
+
+/// The number of photos left to review in a folder changed
+#[derive(Debug, Clone, SimpleObject)]
+pub struct QueueCountChanged {
+    /// Path of the folder relative to the media root
+    pub folder: String,
+    pub count: usize,
+}
+
+#[derive(Debug, Clone)]
+pub enum Event {
+    PhotoReviewed(PhotoReviewed),
+    NewPhotosArrived(NewPhotosArrived),
+    UploadStatusChanged(UploadStatusChanged),
+    QueueCountChanged(QueueCountChanged),
+}
+
+/// Broadcasts the events of a media root to the GraphQL subscriptions
+#[derive(Clone)]
+pub struct Events {
+    sender: broadcast::Sender<Event>,
+}
+
+impl Default for Events {
+    fn default() -> Self {
+        Self {
+            sender: broadcast::channel(EVENT_CAPACITY).0,
+        }
+    }
+}
+
+impl Events {
+    pub fn publish(&self, event: Event) {
+        // without subscribers the event is dropped
+        let _ = self.sender.send(event);
+    }
+
+    /// The events that are published from now on
+    pub fn subscribe(&self) -> impl Stream<Item = Event> + use<> {
+        stream::unfold(self.sender.subscribe(), |mut receiver| async move {
+            loop {
+                match receiver.recv().await {
+                    Ok(event) => return Some((event, receiver)),
+                    Err(RecvError::Lagged(skipped)) => {
+                        error!("Subscriber lagged behind, skipped {skipped} events");
+                    }
+                    Err(RecvError::Closed) => return None,
+                }
+            }
+        })
+    }
+}
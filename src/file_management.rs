@@ -1,95 +1,960 @@
-use crate::fsops::{
-    can_safely_overwrite, chmod, get_unique_filepath, have_equal_contents,
-    rename_with_create_dir_all,
-};
+use crate::auth::{Role, User};
+use crate::deferred::DeferredPhotos;
+use crate::events::{Event, Events, PhotoReviewed, QueueCountChanged};
+use crate::folder_watcher::PendingFolders;
+use crate::fsops::{can_safely_overwrite, chmod, numbered_filepaths, rename_with_create_dir_all};
+use crate::hash_index::HashIndex;
 use crate::image::{
-    Image, ImageToReview, PhotoReview, PhotoReview as ReviewedPhoto, PhotosToReview,
+    DeferredPhoto, DeferredPhotoList, FolderToReview, FoldersToReview, Image, ImageToReview,
+    OrderDirection, PhotoGroup, PhotoOrder, PhotoOrderField, PhotoReview,
+    PhotoReview as ReviewedPhoto, PhotosToReview,
 };
-use crate::reviewscore::{ReviewScore, get_review_scores, get_review_scores_as_str};
+use crate::journal::{Journal, JournalAction, ReviewHistory, ReviewOrigins};
+use crate::live_photo::MotionClips;
+use crate::media_format::{MediaFormat, find_raw_companion, glob_pattern, is_raw_companion};
+use crate::metadata::read_exif_metadata;
+use crate::perceptual_hash::group_similar;
+use crate::preview::PreviewSize;
+use crate::rendition::needs_rendition;
+use crate::reviewscore::{ReviewScore, get_review_scores_as_str};
+use crate::sidecar::{companion_name, find_companions};
+use crate::undo_stack::{MovedFile, PerformedMove, UndoStack};
+use crate::voting::{PhotoVotes, Vote, VoteResult, VotingRule};
+use crate::xmp::{self, XmpMetadata};
 use anyhow::{Context, Result, anyhow, bail};
-use globwalk::GlobWalkerBuilder;
-use std::path::PathBuf;
+use async_graphql::connection::{CursorType, OpaqueCursor, PageInfo};
+use chrono::{DateTime, Utc};
+use globwalk::{FileType, GlobWalkerBuilder};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
 use std::{env, fs};
-use tracing::info;
+use tracing::{error, info};
+
+// folder under the media root that holds the state of photomanager, like the review journal
+pub const STATE_DIR_NAME: &str = ".photomanager";
 
 pub struct FileManager {
     root_dir: String,
+    journal: Journal,
+    undo_stack: Mutex<UndoStack>,
+    hash_index: HashIndex,
+    // collected from the review buckets on first use and kept up to date by the reviews, photos
+    // that are added to the buckets by other means are only found after a restart
+    reviewed_contents: Mutex<Option<ReviewedContents>>,
+    // shared with the watcher, which publishes the queue count of folders with new photos
+    deferred: Arc<Mutex<DeferredPhotos>>,
+    votes: Mutex<PhotoVotes>,
+    // shared with the event subscriptions, which only deliver events of accessible photos
+    origins: Arc<ReviewOrigins>,
+    pending_folders: Option<PendingFolders>,
+    events: Events,
 }
 
 impl FileManager {
-    pub const fn new(media_path: String) -> Self {
+    pub fn new(media_path: String) -> Self {
+        let state_dir = PathBuf::from(&media_path).join(STATE_DIR_NAME);
         Self {
             root_dir: media_path,
+            journal: Journal::new(&state_dir),
+            undo_stack: Mutex::new(UndoStack::load(&state_dir)),
+            hash_index: HashIndex::load(&state_dir),
+            reviewed_contents: Mutex::new(None),
+            deferred: Arc::new(Mutex::new(DeferredPhotos::load(&state_dir))),
+            votes: Mutex::new(PhotoVotes::load(&state_dir)),
+            origins: Arc::new(ReviewOrigins::new(&state_dir)),
+            pending_folders: None,
+            events: Events::default(),
         }
     }
 
+    pub fn events(&self) -> &Events {
+        &self.events
+    }
+
+    /// The folders that the reviewed photos come from, to check access to the review buckets
+    pub fn origins(&self) -> &Arc<ReviewOrigins> {
+        &self.origins
+    }
+
+    /// Watches the media root for new photos, so that the next folder to review is found without
+    /// walking the media root. Without a watcher, like when the platform has no file change
+    /// notifications, the media root is walked.
+    #[must_use]
+    pub fn watching(mut self) -> Self {
+        let root_dir = self.root_dir.clone();
+        let deferred = Arc::clone(&self.deferred);
+        let queue_count = move |folder: &Path| queue_count(&root_dir, folder, &deferred);
+        match PendingFolders::from_env(&self.root_dir, self.events.clone(), queue_count) {
+            Ok(pending_folders) => self.pending_folders = Some(pending_folders),
+            Err(e) => error!(
+                "Failed to watch {}, falling back to walking it: {:#}",
+                self.root_dir, e
+            ),
+        }
+        self
+    }
+
+    /// The photo at the url path under `/media`, which must not leave the media root
     pub fn new_image(&self, relative_path: &str) -> Result<Image> {
+        let is_inside_media_root = Path::new(relative_path)
+            .strip_prefix("/media")
+            .is_ok_and(|path| path.components().all(|c| matches!(c, Component::Normal(_))));
+        if !is_inside_media_root {
+            bail!("Invalid path '{relative_path}'");
+        }
         Image::try_new(relative_path, &self.root_dir)
     }
 }
 
 impl FileManager {
-    pub fn review_photo(&self, review: &PhotoReview) -> Result<ReviewedPhoto> {
+    pub fn review_photo(&self, review: &PhotoReview, user: &User) -> Result<ReviewedPhoto> {
+        let reviewed = self.review_photo_unpublished(review, user)?;
+        self.publish_queue_count(&review.image.full_path);
+        Ok(reviewed)
+    }
+
+    // reviews the photo without publishing the queue count of its folder, for callers that
+    // review several photos of a folder
+    fn review_photo_unpublished(&self, review: &PhotoReview, user: &User) -> Result<ReviewedPhoto> {
         info!("Reviewing photo: {:?}", review);
-        if !PathBuf::from(&review.image.full_path).exists() {
-            bail!("Photo not found: {}", review.image.full_path)
+        user.authorize(
+            review_role(),
+            &self.to_relative_path(&review.image.full_path),
+            &self.origins,
+        )?;
+        let performed = self.move_to_bucket(review, user)?;
+        let mut undo_stack = self.lock_undo_stack()?;
+        let reviewed = self.record_review(&mut undo_stack, performed);
+        undo_stack.save();
+        Ok(reviewed)
+    }
+
+    /// Reviews all photos or none of them. Every review is validated before any photo is
+    /// moved, and when a move fails the photos that were already moved are moved back. The
+    /// result of each review is returned in the order of the reviews.
+    pub fn review_photos(
+        &self,
+        reviews: &[PhotoReview],
+        user: &User,
+    ) -> Vec<Result<ReviewedPhoto>> {
+        info!("Reviewing {} photos", reviews.len());
+        let mut paths = HashSet::new();
+        let validations = reviews
+            .iter()
+            .map(|review| {
+                if !paths.insert(review.image.full_path.as_str()) {
+                    bail!("Photo is reviewed twice: {}", review.image.full_path)
+                }
+                user.authorize(
+                    review_role(),
+                    &self.to_relative_path(&review.image.full_path),
+                    &self.origins,
+                )?;
+                if !PathBuf::from(&review.image.full_path).exists() {
+                    bail!("Photo not found: {}", review.image.full_path)
+                }
+                Ok(())
+            })
+            .collect::<Vec<_>>();
+        if validations.iter().any(Result::is_err) {
+            return validations
+                .into_iter()
+                .map(|validation| {
+                    validation.and_then(|()| {
+                        bail!("Not reviewed because another photo of the batch is invalid")
+                    })
+                })
+                .collect();
         }
 
-        let destination_path = review.get_destination_path();
+        // the undo stack stays locked so that the batch is not interleaved with undo and redo
+        let mut undo_stack = match self.lock_undo_stack() {
+            Ok(undo_stack) => undo_stack,
+            Err(e) => return reviews.iter().map(|_| Err(anyhow!("{e:#}"))).collect(),
+        };
+        let mut performed_moves = vec![];
+        for (index, review) in reviews.iter().enumerate() {
+            match self.move_to_bucket(review, user) {
+                Ok(performed) => performed_moves.push(performed),
+                Err(e) => {
+                    error!("Failed to review {}: {:#}", review.image.full_path, e);
+                    for performed in performed_moves.iter().rev() {
+                        if let Err(e) = self.move_back(performed) {
+                            error!("Failed to roll back {:?}: {:#}", performed, e);
+                        }
+                    }
+                    let mut e = Some(e);
+                    return (0..reviews.len())
+                        .map(|i| match i.cmp(&index) {
+                            Ordering::Less => Err(anyhow!(
+                                "Moved back because another photo of the batch failed"
+                            )),
+                            Ordering::Equal => Err(e.take().unwrap_or_else(|| anyhow!("failed"))),
+                            Ordering::Greater => Err(anyhow!(
+                                "Not reviewed because another photo of the batch failed"
+                            )),
+                        })
+                        .collect();
+                }
+            }
+        }
+        let reviewed = performed_moves
+            .into_iter()
+            .map(|performed| Ok(self.record_review(&mut undo_stack, performed)))
+            .collect();
+        undo_stack.save();
+        drop(undo_stack);
+        let folders = reviews
+            .iter()
+            .filter_map(|review| Path::new(&review.image.full_path).parent())
+            .collect::<BTreeSet<_>>();
+        for folder in folders {
+            self.publish_folder_queue_count(folder);
+        }
+        reviewed
+    }
 
-        Self::move_file_prevent_overwrite_different_contents(
+    /// Reviews all photos that are left in the folder, relative to the media root, with the same
+    /// score. Every photo is reviewed like `review_photo` does, a photo that fails does not stop
+    /// the others. Like in photosToReview, deferred photos are skipped and photos that have been
+    /// reviewed before are moved to the already_reviewed bucket instead.
+    pub fn finish_folder(
+        &self,
+        folder: &str,
+        score: ReviewScore,
+        user: &User,
+    ) -> Result<Vec<(Image, Result<ReviewedPhoto>)>> {
+        user.authorize(review_role(), folder, &self.origins)?;
+        let folder_path = self.resolve_folder_to_review(folder)?;
+        info!(
+            "Finishing folder {} with score {}",
+            folder_path,
+            score.name()
+        );
+        let mut images = list_folder_images(&folder_path)?;
+        images.sort();
+        self.load_reviewed_contents()?;
+        let results = images
+            .into_iter()
+            .filter(|path| !self.is_deferred(path))
+            .filter_map(|path| Some(Image::from_full_path(path.to_str()?, &self.root_dir)))
+            .filter(|image| !self.move_if_already_reviewed(image))
+            .map(|image| {
+                let result = self.review_photo_unpublished(
+                    &PhotoReview {
+                        image: image.clone(),
+                        score,
+                    },
+                    user,
+                );
+                (image, result)
+            })
+            .collect();
+        self.hash_index.save();
+        self.publish_folder_queue_count(Path::new(&folder_path));
+        Ok(results)
+    }
+
+    // moves the photo to the bucket of its score, without registering the review yet
+    fn move_to_bucket(&self, review: &PhotoReview, user: &User) -> Result<PerformedMove> {
+        if !PathBuf::from(&review.image.full_path).exists() {
+            bail!("Photo not found: {}", review.image.full_path)
+        }
+        let (photo, companions) = self.move_file_prevent_overwrite_different_contents(
             &review.image.full_path,
-            &destination_path,
-        )
-        .map(|()| ReviewedPhoto {
-            image: Image::from_full_path(&destination_path, &self.root_dir),
+            &review.get_destination_path(),
+        )?;
+        Ok(PerformedMove {
+            source: photo.source,
+            destination: photo.destination,
+            replaced: photo.replaced,
             score: review.score,
+            companions,
+            reviewer: Some(user.name.clone()),
         })
     }
 
+    // journals the review and makes it undoable
+    fn record_review(&self, undo_stack: &mut UndoStack, performed: PerformedMove) -> ReviewedPhoto {
+        self.write_journal(
+            JournalAction::Review,
+            &performed.source,
+            &performed.destination,
+            performed.score,
+        );
+        let reviewed = ReviewedPhoto {
+            image: Image::from_full_path(&performed.destination, &self.root_dir),
+            score: performed.score,
+        };
+        self.update_reviewed_contents(|contents| contents.insert(&performed.destination));
+        if let Ok(mut deferred) = self.lock_deferred()
+            && deferred.undefer(&self.to_relative_path(&performed.source))
+        {
+            deferred.save(&self.root_dir);
+        }
+        if let Ok(mut votes) = self.lock_votes()
+            && votes.remove(&self.to_relative_path(&performed.source))
+        {
+            votes.save(&self.root_dir);
+        }
+        self.publish_review(&performed.source, &performed.destination, performed.score);
+        undo_stack.push(performed);
+        reviewed
+    }
+
+    // moves the photo together with its companion files, which get the same name as the photo
+    // at the destination. Returns the move of the photo, with the path that it was actually
+    // moved to, and the moved companions.
     fn move_file_prevent_overwrite_different_contents(
+        &self,
         source_file: &str,
         destination_file: &str,
-    ) -> Result<()> {
-        let mut final_destination_file = destination_file.into();
-        if !can_safely_overwrite(source_file, destination_file)? {
-            final_destination_file = get_unique_filepath(destination_file)?;
+    ) -> Result<(MovedFile, Vec<MovedFile>)> {
+        // the photo and its companions get the same unique name, so that they stay together
+        let mut candidates = std::iter::once(destination_file.to_string())
+            .chain(numbered_filepaths(destination_file)?);
+        let (final_destination_file, companions) = loop {
+            let Some(candidate) = candidates.next() else {
+                bail!("Failed to find unique file path for: {}", destination_file)
+            };
+            let companions = companion_moves(source_file, &candidate);
+            if self.can_move_all(source_file, &candidate, &companions)? {
+                break (candidate, companions);
+            }
+        };
+        if final_destination_file != destination_file {
             info!(
                 "Destination file already exists, but contents are different. Moving to {}",
                 final_destination_file
             );
         }
-        rename_with_create_dir_all(source_file, &final_destination_file, 0o775)?;
-        chmod(&final_destination_file, 0o775)
+
+        let photo = MovedFile {
+            replaced: PathBuf::from(&final_destination_file).exists(),
+            source: source_file.into(),
+            destination: final_destination_file,
+        };
+        self.rename(&photo.source, &photo.destination)?;
+        chmod(&photo.destination, 0o775)?;
+        for (i, companion) in companions.iter().enumerate() {
+            if let Err(e) = self
+                .rename(&companion.source, &companion.destination)
+                .and_then(|()| chmod(&companion.destination, 0o775))
+            {
+                // move the files back so that the photo is not separated from its companions
+                for moved in companions[..i].iter().chain([&photo]) {
+                    if let Err(e) = self.move_file_back(moved) {
+                        error!("Failed to move {} back: {:#}", moved.destination, e);
+                    }
+                }
+                return Err(e);
+            }
+        }
+        Ok((photo, companions))
+    }
+
+    // whether the photo and its companions can be moved without overwriting different files
+    fn can_move_all(
+        &self,
+        source_file: &str,
+        destination_file: &str,
+        companions: &[MovedFile],
+    ) -> Result<bool> {
+        if !can_safely_overwrite(source_file, destination_file, &self.hash_index)? {
+            return Ok(false);
+        }
+        for companion in companions {
+            if !can_safely_overwrite(&companion.source, &companion.destination, &self.hash_index)? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    fn rename(&self, source_file: &str, destination_file: &str) -> Result<()> {
+        rename_with_create_dir_all(source_file, destination_file, 0o775)?;
+        self.hash_index.moved(source_file, destination_file);
+        Ok(())
+    }
+
+    /// Writes the rating, label and tags to the XMP sidecar of the photo. When a score is given,
+    /// the photo is reviewed as well, which moves the sidecar along with it.
+    pub fn rate_photo(
+        &self,
+        image: &Image,
+        stars: Option<i32>,
+        label: Option<String>,
+        tags: Option<Vec<String>>,
+        score: Option<ReviewScore>,
+        user: &User,
+    ) -> Result<Option<ReviewedPhoto>> {
+        user.authorize(
+            Role::Reviewer,
+            &self.to_relative_path(&image.full_path),
+            &self.origins,
+        )?;
+        if !PathBuf::from(&image.full_path).exists() {
+            bail!("Photo not found: {}", image.full_path)
+        }
+        let metadata = xmp::read_sidecar(&image.full_path)?.merge(stars, label, tags);
+        info!("Rating photo {}: {:?}", image.full_path, metadata);
+        xmp::write_sidecar(&image.full_path, &metadata)?;
+
+        score
+            .map(|score| {
+                self.review_photo(
+                    &PhotoReview {
+                        image: image.clone(),
+                        score,
+                    },
+                    user,
+                )
+            })
+            .transpose()
     }
 
-    pub fn undo(review: &PhotoReview) -> Result<()> {
+    /// Undoes the most recent review of the photo. The destination is taken from the undo
+    /// history when available, as the photo might have been given a unique name. Reviewers can
+    /// only undo their own reviews, photos that are not in the undo history can only be restored
+    /// by admins.
+    pub fn undo(&self, review: &PhotoReview, user: &User) -> Result<()> {
         info!("undoing review: {:?}", review);
-        let destination_file = review.get_destination_path();
-        if !PathBuf::from(&destination_file).exists() {
-            bail!("Cannot undo, photo at [{}] not found", &destination_file)
+        user.authorize(
+            Role::Reviewer,
+            &self.to_relative_path(&review.image.full_path),
+            &self.origins,
+        )?;
+        let mut undo_stack = self.lock_undo_stack()?;
+        let source = &review.image.full_path;
+        let performed = match undo_stack.take_undo(|m| m.source == *source) {
+            Some(performed) if !may_undo(user, &performed) => {
+                let reviewer = performed
+                    .reviewer
+                    .clone()
+                    .unwrap_or_else(|| "unknown".into());
+                undo_stack.push_undo(performed);
+                bail!("{} cannot undo the review of {}", user.name, reviewer)
+            }
+            Some(performed) => performed,
+            None => {
+                user.require(Role::Admin)?;
+                let destination = review.get_destination_path();
+                PerformedMove {
+                    companions: companion_moves(&destination, &review.image.full_path)
+                        .into_iter()
+                        .map(|m| MovedFile {
+                            source: m.destination,
+                            destination: m.source,
+                            replaced: false,
+                        })
+                        .collect(),
+                    source: review.image.full_path.clone(),
+                    destination,
+                    replaced: false,
+                    score: review.score,
+                    reviewer: None,
+                }
+            }
+        };
+        self.revert_move(&mut undo_stack, performed)?;
+        drop(undo_stack);
+        self.publish_queue_count(source);
+        Ok(())
+    }
+
+    /// Undoes the most recent review of the user and returns the restored photo
+    pub fn undo_last(&self, user: &User) -> Result<Image> {
+        user.require(Role::Reviewer)?;
+        let mut undo_stack = self.lock_undo_stack()?;
+        let performed = undo_stack
+            .take_undo(|m| is_own_review(user, m))
+            .context("Nothing to undo")?;
+        info!("undoing last review: {:?}", performed);
+        let image = Image::from_full_path(&performed.source, &self.root_dir);
+        self.revert_move(&mut undo_stack, performed)?;
+        drop(undo_stack);
+        self.publish_queue_count(&image.full_path);
+        Ok(image)
+    }
+
+    /// Applies the most recently undone review of the user again. Returns the photo as it was
+    /// before the review and the reviewed photo.
+    pub fn redo(&self, user: &User) -> Result<(Image, ReviewedPhoto)> {
+        user.require(Role::Reviewer)?;
+        let mut undo_stack = self.lock_undo_stack()?;
+        let performed = undo_stack
+            .take_redo(|m| is_own_review(user, m))
+            .context("Nothing to redo")?;
+        info!("redoing review: {:?}", performed);
+        let result = self.move_file_prevent_overwrite_different_contents(
+            &performed.source,
+            &performed.destination,
+        );
+        let (photo, companions) = match result {
+            Ok(moved) => moved,
+            Err(e) => {
+                if PathBuf::from(&performed.source).exists() {
+                    undo_stack.push_redo(performed);
+                }
+                undo_stack.save();
+                return Err(e);
+            }
+        };
+        let destination_path = photo.destination;
+        self.write_journal(
+            JournalAction::Redo,
+            &performed.source,
+            &destination_path,
+            performed.score,
+        );
+        self.update_reviewed_contents(|contents| contents.insert(&destination_path));
+        undo_stack.push_undo(PerformedMove {
+            destination: destination_path.clone(),
+            replaced: photo.replaced,
+            companions,
+            ..performed.clone()
+        });
+        undo_stack.save();
+        drop(undo_stack);
+        self.publish_review(&performed.source, &destination_path, performed.score);
+        self.publish_queue_count(&performed.source);
+
+        Ok((
+            Image::from_full_path(&performed.source, &self.root_dir),
+            ReviewedPhoto {
+                image: Image::from_full_path(&destination_path, &self.root_dir),
+                score: performed.score,
+            },
+        ))
+    }
+
+    fn revert_move(&self, undo_stack: &mut UndoStack, performed: PerformedMove) -> Result<()> {
+        let result = if !PathBuf::from(&performed.destination).exists() {
+            Err(anyhow!(
+                "Cannot undo, photo at [{}] not found",
+                &performed.destination
+            ))
+        } else if let Some(existing) = std::iter::once(&performed.source)
+            .chain(performed.companions.iter().map(|c| &c.source))
+            .find(|source| PathBuf::from(source).exists())
+        {
+            Err(anyhow!(
+                "Cannot undo, a file already exists at [{existing}]"
+            ))
+        } else {
+            self.move_back(&performed)
+        };
+
+        match result {
+            Ok(()) => {
+                self.write_journal(
+                    JournalAction::Undo,
+                    &performed.destination,
+                    &performed.source,
+                    performed.score,
+                );
+                self.update_reviewed_contents(|contents| contents.remove(&performed.destination));
+                undo_stack.push_redo(performed);
+                undo_stack.save();
+                Ok(())
+            }
+            Err(e) => {
+                // keep the review in the history as long as it can still be undone later
+                if PathBuf::from(&performed.destination).exists() {
+                    undo_stack.push_undo(performed);
+                }
+                undo_stack.save();
+                Err(e)
+            }
+        }
+    }
+
+    // moves the photo and its companions back to where they were before the move. When a file
+    // cannot be moved back, the files that were already moved back are moved forward again, so
+    // that the photo is not separated from its companions and the move can be reverted later.
+    fn move_back(&self, performed: &PerformedMove) -> Result<()> {
+        let photo = performed.photo();
+        let files = std::iter::once(&photo).chain(
+            performed
+                .companions
+                .iter()
+                // a companion that has been removed since the review stays removed
+                .filter(|c| PathBuf::from(&c.destination).exists()),
+        );
+        let mut moved_back: Vec<&MovedFile> = vec![];
+        for file in files {
+            if let Err(e) = self.move_file_back(file) {
+                for moved in moved_back.iter().rev() {
+                    if let Err(e) = self.move_file_forward_again(moved) {
+                        error!("Failed to move {} forward again: {:#}", moved.source, e);
+                    }
+                }
+                return Err(e);
+            }
+            moved_back.push(file);
+        }
+        Ok(())
+    }
+
+    // a file that replaced an identical file is copied back, so that the file that was already
+    // at the destination before the move stays there
+    fn move_file_back(&self, file: &MovedFile) -> Result<()> {
+        if !file.replaced {
+            return self.rename(&file.destination, &file.source);
+        }
+        if let Some(parent) = Path::new(&file.source).parent() {
+            fs::create_dir_all(parent)?;
+        }
+        info!("Copying photo from {} to {}", file.destination, file.source);
+        fs::copy(&file.destination, &file.source).with_context(|| {
+            format!(
+                "Failed to copy photo from {} to {}",
+                file.destination, file.source
+            )
+        })?;
+        Ok(())
+    }
+
+    fn move_file_forward_again(&self, file: &MovedFile) -> Result<()> {
+        if file.replaced {
+            fs::remove_file(&file.source)
+                .with_context(|| format!("Failed to remove {}", file.source))
+        } else {
+            self.rename(&file.source, &file.destination)
+        }
+    }
+
+    /// Postpones the review of the photo: it stays where it is, but photosToReview skips it
+    pub fn defer_photo(&self, image: &Image, user: &User) -> Result<()> {
+        info!("Deferring photo {}", image.full_path);
+        user.authorize(
+            Role::Reviewer,
+            &self.to_relative_path(&image.full_path),
+            &self.origins,
+        )?;
+        if !PathBuf::from(&image.full_path).exists() {
+            bail!("Photo not found: {}", image.full_path)
+        }
+        let mut deferred = self.lock_deferred()?;
+        deferred.defer(&self.to_relative_path(&image.full_path));
+        deferred.save(&self.root_dir);
+        drop(deferred);
+        self.publish_queue_count(&image.full_path);
+        Ok(())
+    }
+
+    /// Returns the photo to the review queue
+    pub fn undefer_photo(&self, image: &Image, user: &User) -> Result<()> {
+        info!("Undeferring photo {}", image.full_path);
+        user.authorize(
+            Role::Reviewer,
+            &self.to_relative_path(&image.full_path),
+            &self.origins,
+        )?;
+        let mut deferred = self.lock_deferred()?;
+        if !deferred.undefer(&self.to_relative_path(&image.full_path)) {
+            bail!("Photo is not deferred: {}", image.full_path)
+        }
+        deferred.save(&self.root_dir);
+        drop(deferred);
+        self.publish_queue_count(&image.full_path);
+        Ok(())
+    }
+
+    /// Casts the vote of the user on the score of the photo, replacing an earlier vote of the
+    /// user. The photo stays where it is until the votes resolve according to the configured
+    /// voting rule, then it is reviewed with the resolved score. A vote on a photo that has been
+    /// reviewed just before, like by a concurrent vote, returns the score it was reviewed with.
+    pub fn vote_photo(
+        &self,
+        image: &Image,
+        score: ReviewScore,
+        user: &User,
+    ) -> Result<(VoteResult, Option<ReviewedPhoto>)> {
+        info!(
+            "{} votes {} for photo {}",
+            user.name,
+            score.name(),
+            image.full_path
+        );
+        let relative_path = self.to_relative_path(&image.full_path);
+        user.authorize(Role::Reviewer, &relative_path, &self.origins)?;
+        let rule = VotingRule::configured()
+            .context("Voting is not enabled, set VOTING_CONFIG to enable it")?;
+        // the undo stack stays locked until the vote is applied, so that concurrent votes are
+        // counted one after the other. The votes are locked after it, like a review does.
+        let mut undo_stack = self.lock_undo_stack()?;
+        if !PathBuf::from(&image.full_path).exists() {
+            // another vote may have resolved the photo just before
+            if let Some(performed) = undo_stack.find_undo(|m| m.source == image.full_path) {
+                return Ok((
+                    VoteResult {
+                        votes: vec![],
+                        resolved_score: Some(performed.score),
+                        destination: Some(
+                            Image::from_full_path(&performed.destination, &self.root_dir).url(),
+                        ),
+                        message: None,
+                    },
+                    None,
+                ));
+            }
+            bail!("Photo not found: {}", image.full_path)
+        }
+        let mut votes = self.lock_votes()?;
+        let cast = votes.vote(&relative_path, &user.name, score).to_vec();
+        votes.save(&self.root_dir);
+        drop(votes);
+
+        let scores = cast.iter().map(|vote| vote.score).collect::<Vec<_>>();
+        let Some(resolved_score) = rule.resolve(&scores) else {
+            return Ok((
+                VoteResult {
+                    votes: cast,
+                    resolved_score: None,
+                    destination: None,
+                    message: None,
+                },
+                None,
+            ));
+        };
+        info!(
+            "Votes on {} resolved to {}",
+            image.full_path,
+            resolved_score.name()
+        );
+        let performed = self.move_to_bucket(
+            &PhotoReview {
+                image: image.clone(),
+                score: resolved_score,
+            },
+            user,
+        )?;
+        let reviewed = self.record_review(&mut undo_stack, performed);
+        undo_stack.save();
+        drop(undo_stack);
+        self.publish_queue_count(&image.full_path);
+        Ok((
+            VoteResult {
+                votes: cast,
+                resolved_score: Some(resolved_score),
+                destination: Some(reviewed.image.url()),
+                message: None,
+            },
+            Some(reviewed),
+        ))
+    }
+
+    /// The deferred photos, optionally only those in the folder relative to the media root
+    pub fn get_deferred_photos(
+        &self,
+        folder: Option<&str>,
+        user: &User,
+    ) -> Result<DeferredPhotoList> {
+        let folder = folder.map(|f| Path::new(f.trim_matches('/')));
+        let photos = self
+            .lock_deferred()?
+            .iter()
+            .filter(|(relative_path, _)| {
+                folder.is_none_or(|folder| Path::new(relative_path).parent() == Some(folder))
+                    && user.can_access(Path::new(relative_path), &self.origins)
+            })
+            .filter_map(|(relative_path, deferred_at)| {
+                let full_path = PathBuf::from(&self.root_dir).join(relative_path);
+                full_path.exists().then(|| {
+                    let image = Image::from_full_path(&full_path.to_string_lossy(), &self.root_dir);
+                    DeferredPhoto {
+                        url: image.url(),
+                        album: image.album_name,
+                        deferred_at: *deferred_at,
+                    }
+                })
+            })
+            .collect();
+        Ok(DeferredPhotoList { photos })
+    }
+
+    fn publish_review(&self, source: &str, destination: &str, score: ReviewScore) {
+        self.events.publish(Event::PhotoReviewed(PhotoReviewed {
+            path: Image::from_full_path(source, &self.root_dir).url(),
+            destination: Image::from_full_path(destination, &self.root_dir).url(),
+            score,
+        }));
+    }
+
+    // publishes the number of photos left to review in the folder of the photo. This lists the
+    // folder, so it is published once per folder and without holding the undo stack.
+    fn publish_queue_count(&self, photo_path: &str) {
+        if let Some(folder) = Path::new(photo_path).parent() {
+            self.publish_folder_queue_count(folder);
+        }
+    }
+
+    fn publish_folder_queue_count(&self, folder: &Path) {
+        self.events
+            .publish(Event::QueueCountChanged(QueueCountChanged {
+                folder: self.to_relative_path(&folder.to_string_lossy()),
+                count: queue_count(&self.root_dir, folder, &self.deferred),
+            }));
+    }
+
+    fn lock_deferred(&self) -> Result<MutexGuard<'_, DeferredPhotos>> {
+        self.deferred
+            .lock()
+            .map_err(|e| anyhow!("deferred photos lock poisoned: {e}"))
+    }
+
+    fn lock_votes(&self) -> Result<MutexGuard<'_, PhotoVotes>> {
+        self.votes
+            .lock()
+            .map_err(|e| anyhow!("votes lock poisoned: {e}"))
+    }
+
+    fn get_votes(&self, full_path: &str) -> Vec<Vote> {
+        self.lock_votes().map_or_else(
+            |_| vec![],
+            |votes| votes.get(&self.to_relative_path(full_path)).to_vec(),
+        )
+    }
+
+    fn is_deferred(&self, full_path: &Path) -> bool {
+        self.lock_deferred().is_ok_and(|deferred| {
+            deferred.contains(&self.to_relative_path(&full_path.to_string_lossy()))
+        })
+    }
+
+    fn lock_undo_stack(&self) -> Result<MutexGuard<'_, UndoStack>> {
+        self.undo_stack
+            .lock()
+            .map_err(|e| anyhow!("undo stack lock poisoned: {e}"))
+    }
+
+    /// The reviews of the photos that the user has access to
+    pub fn get_review_history(
+        &self,
+        limit: usize,
+        cursor: Option<u64>,
+        user: &User,
+    ) -> Result<ReviewHistory> {
+        self.journal.history(limit, cursor, |entry| {
+            // the path of the photo before it was reviewed
+            let unreviewed_path = match entry.action {
+                JournalAction::Review | JournalAction::Redo => &entry.source,
+                JournalAction::Undo => &entry.destination,
+            };
+            user.can_access(Path::new(unreviewed_path), &self.origins)
+        })
+    }
+
+    // the file has already been moved at this point, so a failing journal write is logged
+    // instead of reported as a failed review
+    fn write_journal(
+        &self,
+        action: JournalAction,
+        source_file: &str,
+        destination_file: &str,
+        score: ReviewScore,
+    ) {
+        if let Err(e) = self.journal.append(
+            action,
+            &self.to_relative_path(source_file),
+            &self.to_relative_path(destination_file),
+            score,
+        ) {
+            error!(
+                "Failed to write {:?} of {} to the journal: {:#}",
+                action, source_file, e
+            );
         }
-        rename_with_create_dir_all(&destination_file, &review.image.full_path, 0o775)
     }
+
+    fn to_url(&self, path: &Path) -> Option<String> {
+        Some(Image::from_full_path(path.to_str()?, &self.root_dir).url())
+    }
+
+    fn to_relative_path(&self, full_path: &str) -> String {
+        Path::new(full_path)
+            .strip_prefix(&self.root_dir)
+            .map_or_else(|_| full_path.into(), |p| p.to_string_lossy().into())
+    }
+}
+
+/// Selects the photos that `get_photos_to_review` returns
+pub struct PhotosToReviewRequest {
+    /// Folder relative to the media root, defaults to the first folder with images to review
+    pub folder: Option<String>,
+    pub order: PhotoOrder,
+    pub first: usize,
+    /// Position of the photo after which the page starts
+    pub after: Option<QueueCursor>,
+    pub user: User,
+}
+
+/// Position of a photo in the review queue: the value of the ordering field, with the relative
+/// path as tie-breaker so that the ordering is stable
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct QueueCursor {
+    key: i64,
+    path: String,
+}
+
+struct ImageFilesPage {
+    folder_image_count: usize,
+    image_files: Vec<(QueueCursor, Image)>,
+    has_next_page: bool,
 }
 
 // get_photos_to_review implementation
 impl FileManager {
-    pub fn get_photos_to_review(&self) -> Result<PhotosToReview> {
-        let (folder_image_count, image_files) = self
-            .find_image_files()
+    /// Returns at most `first` photos of the folder to review, that come after the `after` cursor
+    /// in the requested ordering
+    pub fn get_photos_to_review(&self, request: &PhotosToReviewRequest) -> Result<PhotosToReview> {
+        let ImageFilesPage {
+            folder_image_count,
+            image_files,
+            has_next_page,
+        } = self
+            .find_image_files(request)
             .with_context(|| "failed to find image files")?;
 
         let folder_name = image_files
             .iter()
+            .map(|(_, image)| image)
             .find(|p| !p.album_name.is_empty())
             .map_or_else(|| "unknown".into(), |p| p.album_name.clone());
 
+        let motion_clips = MotionClips::default();
         let photos = image_files
             .iter()
-            .map(|f| {
+            .map(|(cursor, f)| {
+                let metadata = xmp::read_sidecar(&f.full_path).unwrap_or_else(|e| {
+                    error!("Failed to read XMP sidecar of {}: {:#}", f.full_path, e);
+                    XmpMetadata::default()
+                });
                 Ok(ImageToReview {
-                    url: "/media/".to_string() + &f.relative_path,
+                    cursor: OpaqueCursor(cursor.clone()).encode_cursor(),
+                    url: if MediaFormat::detect(Path::new(&f.full_path))
+                        .is_some_and(needs_rendition)
+                    {
+                        PreviewSize::Full.url(&f.relative_path)
+                    } else {
+                        f.url()
+                    },
+                    thumbnail_url: PreviewSize::Thumbnail.url(&f.relative_path),
+                    preview_url: PreviewSize::Large.url(&f.relative_path),
+                    raw_url: find_raw_companion(Path::new(&f.full_path))
+                        .and_then(|raw| self.to_url(&raw)),
+                    motion_url: motion_clips
+                        .find(Path::new(&f.full_path))
+                        .and_then(|clip| self.to_url(&clip)),
+                    rating: metadata.rating,
+                    label: metadata.label,
+                    tags: metadata.tags,
+                    votes: self.get_votes(&f.full_path),
+                    exif: read_exif_metadata(&f.full_path),
                     album: PathBuf::from(&f.full_path)
                         .parent()
                         .context("Failed to get parent directory")?
@@ -100,84 +965,466 @@ impl FileManager {
             })
             .collect::<Result<Vec<ImageToReview>>>()?;
 
+        let groups = group_similar(
+            image_files.iter().zip(photos.iter()).collect(),
+            |((_, image), _)| image.perceptual_hash,
+        )
+        .into_iter()
+        .map(|group| PhotoGroup {
+            photos: group.into_iter().map(|(_, photo)| photo.clone()).collect(),
+        })
+        .collect();
+
+        let page_info = PageInfo {
+            has_previous_page: request.after.is_some(),
+            has_next_page,
+            start_cursor: photos.first().map(|p| p.cursor.clone()),
+            end_cursor: photos.last().map(|p| p.cursor.clone()),
+        };
+
         Ok(PhotosToReview {
             base_url: env::var("PUBLIC_URL")
                 .context("'PUBLIC_URL' environment variable is required")?,
             photos,
+            page_info,
+            groups,
             folder_image_count,
             folder_name,
         })
     }
 
-    fn find_image_files(&self) -> Result<(usize, Vec<Image>)> {
-        let folder_with_review_images = self.find_next_folder_path_with_images_to_review()?;
+    fn find_image_files(&self, request: &PhotosToReviewRequest) -> Result<ImageFilesPage> {
+        let folder_with_review_images = match &request.folder {
+            Some(folder) => {
+                request
+                    .user
+                    .authorize(Role::Viewer, folder, &self.origins)?;
+                self.resolve_folder_to_review(folder)?
+            }
+            None => self.find_next_folder_path_with_images_to_review(&request.user)?,
+        };
+        let first = request.first;
 
-        let mut image_files = fs::read_dir(folder_with_review_images)?
-            .filter_map(Result::ok)
-            .filter(|entry| {
-                let path = entry.path();
-                path.is_file()
-                    && path.extension().map_or_else(
-                        || false,
-                        |ext| ext == "png" || ext == "jpg" || ext == "jpeg" || ext == "gif",
-                    )
+        let mut image_files = list_folder_images(&folder_with_review_images)?
+            .into_iter()
+            .filter(|path| !self.is_deferred(path))
+            .map(|path| {
+                let path: String = path.to_str().unwrap().into();
+                QueueCursor {
+                    key: self.get_sort_key(&path, request.order.field),
+                    path: self.to_relative_path(&path),
+                }
             })
-            .map(|entry| entry.path().to_str().unwrap().into())
-            .collect::<Vec<String>>();
+            .collect::<Vec<QueueCursor>>();
 
         image_files.sort();
+        if request.order.direction == OrderDirection::Desc {
+            image_files.reverse();
+        }
 
         let folder_image_count = image_files.len();
 
-        let image_files = image_files
+        self.load_reviewed_contents()?;
+        let mut image_files = image_files
             .into_iter()
-            .map(|path| Image::from_full_path(&path, &self.root_dir))
-            // exclude all images that have already been reviewed
-            .filter(|img| {
-                !get_review_scores().iter().any(|score| {
-                    if have_equal_contents(&img.full_path, &img.get_destination_path(*score))
-                        .unwrap_or(false)
-                    {
-                        // move images that are already reviewed to the already_reviewed bucket
-                        return rename_with_create_dir_all(
-                            &img.full_path,
-                            &img.get_destination_path(ReviewScore::AlreadyReviewed),
-                            0o775,
-                        )
-                        // if the image was moved successfully, it shouldn't be reviewed
-                        // anymore
-                        .is_ok();
-                    }
-                    false
-                })
+            .filter(|cursor| {
+                request
+                    .after
+                    .as_ref()
+                    .is_none_or(|after| match request.order.direction {
+                        OrderDirection::Asc => cursor > after,
+                        OrderDirection::Desc => cursor < after,
+                    })
             })
-            .take(20)
-            .collect();
+            .map(|cursor| {
+                let image = Image::from_full_path(
+                    &PathBuf::from(&self.root_dir)
+                        .join(&cursor.path)
+                        .to_string_lossy(),
+                    &self.root_dir,
+                );
+                (cursor, image)
+            })
+            .filter(|(_, img)| !self.move_if_already_reviewed(img))
+            // one more than requested to find out if there is a next page
+            .take(first + 1)
+            .collect::<Vec<(QueueCursor, Image)>>();
+        let has_next_page = image_files.len() > first;
+        image_files.truncate(first);
+
+        for (_, img) in &mut image_files {
+            img.perceptual_hash = self
+                .hash_index
+                .perceptual_hash(&img.full_path)
+                .unwrap_or_else(|e| {
+                    error!(
+                        "Failed to get perceptual hash of {}: {:#}",
+                        img.full_path, e
+                    );
+                    None
+                });
+        }
+        self.hash_index.save();
+
+        Ok(ImageFilesPage {
+            folder_image_count,
+            image_files,
+            has_next_page,
+        })
+    }
+
+    // photos without EXIF capture time are ordered by their modification time
+    fn get_sort_key(&self, path: &str, field: PhotoOrderField) -> i64 {
+        let modified_time =
+            || -> Option<DateTime<Utc>> { Some(fs::metadata(path).ok()?.modified().ok()?.into()) };
+        match field {
+            PhotoOrderField::FileName => 0,
+            PhotoOrderField::FileSize => {
+                fs::metadata(path).map_or(0, |m| i64::try_from(m.len()).unwrap_or(i64::MAX))
+            }
+            PhotoOrderField::ModifiedTime => modified_time()
+                .and_then(|t| t.timestamp_nanos_opt())
+                .unwrap_or_default(),
+            PhotoOrderField::CaptureTime => self
+                .hash_index
+                .capture_time(path)
+                .unwrap_or_else(|e| {
+                    error!("Failed to get capture time of {}: {:#}", path, e);
+                    None
+                })
+                .or_else(modified_time)
+                .and_then(|t| t.timestamp_nanos_opt())
+                .unwrap_or_default(),
+        }
+    }
+
+    // moves a photo that has already been reviewed, also when it was reviewed under a different
+    // album folder, to the already_reviewed bucket. Returns whether it was moved, a photo that
+    // could not be moved is still to be reviewed.
+    fn move_if_already_reviewed(&self, img: &Image) -> bool {
+        if !self.is_reviewed_before(&img.full_path) {
+            return false;
+        }
+        match self.move_file_prevent_overwrite_different_contents(
+            &img.full_path,
+            &img.get_destination_path(ReviewScore::already_reviewed()),
+        ) {
+            Ok((photo, _)) => {
+                self.update_reviewed_contents(|contents| contents.insert(&photo.destination));
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    // whether a photo with the same contents is in one of the review buckets
+    fn is_reviewed_before(&self, file_path: &str) -> bool {
+        self.reviewed_contents.lock().is_ok_and(|contents| {
+            contents
+                .as_ref()
+                .is_some_and(|contents| contents.contains(file_path, &self.hash_index))
+        })
+    }
+
+    // collects the photos in the review buckets, unless they have been collected before
+    fn load_reviewed_contents(&self) -> Result<()> {
+        let mut reviewed_contents = self
+            .reviewed_contents
+            .lock()
+            .map_err(|_| anyhow!("Failed to lock the reviewed contents"))?;
+        if reviewed_contents.is_none() {
+            *reviewed_contents = Some(self.get_reviewed_contents()?);
+        }
+        Ok(())
+    }
 
-        Ok((folder_image_count, image_files))
+    fn update_reviewed_contents(&self, update: impl FnOnce(&mut ReviewedContents)) {
+        if let Ok(mut reviewed_contents) = self.reviewed_contents.lock()
+            && let Some(reviewed_contents) = reviewed_contents.as_mut()
+        {
+            update(reviewed_contents);
+        }
+    }
+
+    // collects the sizes of all photos in the review buckets, the photos are only hashed when a
+    // photo to review has the same size. Hashes of photos that are no longer in the buckets are
+    // removed from the hash index.
+    fn get_reviewed_contents(&self) -> Result<ReviewedContents> {
+        let mut reviewed_contents = ReviewedContents::default();
+        let mut bucket_dirs = Vec::new();
+        for score in get_review_scores_as_str() {
+            let bucket_dir = PathBuf::from(&self.root_dir).join(score);
+            if !bucket_dir.exists() {
+                continue;
+            }
+            for entry in GlobWalkerBuilder::from_patterns(&bucket_dir, &[glob_pattern()])
+                .case_insensitive(true)
+                .file_type(FileType::FILE)
+                .build()?
+                .filter_map(Result::ok)
+            {
+                let path = entry.path().to_str().context("to_str failed")?;
+                reviewed_contents.add(path, entry.metadata()?.len());
+            }
+            bucket_dirs.push(bucket_dir);
+        }
+        self.hash_index.prune(|path| {
+            bucket_dirs.iter().any(|dir| path.starts_with(dir))
+                && path
+                    .extension()
+                    .and_then(|e| e.to_str())
+                    .and_then(MediaFormat::from_extension)
+                    .is_some()
+                && path
+                    .to_str()
+                    .is_none_or(|path| !reviewed_contents.sizes.contains_key(path))
+        });
+        Ok(reviewed_contents)
     }
 
-    fn find_next_folder_path_with_images_to_review(&self) -> Result<String> {
-        let mut excludes: Vec<String> = vec![format!("**/{}", "*.{png,jpg,jpeg,gif}")];
+    /// The folders with images to review that the user has access to
+    pub fn get_folders_to_review(&self, user: &User) -> Result<FoldersToReview> {
+        let mut folders: BTreeMap<PathBuf, FolderToReview> = BTreeMap::new();
+        for img in self
+            .walk_images_to_review(user)?
+            .filter(|img| !is_raw_companion(img.path()))
+        {
+            let Some(folder) = img.path().parent() else {
+                continue;
+            };
+            let modified: DateTime<Utc> = img.metadata()?.modified()?.into();
+            folders
+                .entry(folder.into())
+                .and_modify(|f| {
+                    f.image_count += 1;
+                    f.oldest = f.oldest.min(modified);
+                    f.newest = f.newest.max(modified);
+                })
+                .or_insert_with(|| FolderToReview {
+                    path: self.to_relative_path(&folder.to_string_lossy()),
+                    name: folder
+                        .file_name()
+                        .map_or_else(String::new, |n| n.to_string_lossy().into()),
+                    image_count: 1,
+                    oldest: modified,
+                    newest: modified,
+                });
+        }
+        Ok(FoldersToReview {
+            folders: folders.into_values().collect(),
+        })
+    }
+
+    // the images under the media root that the user has access to, excluding the review buckets
+    fn walk_images_to_review(
+        &self,
+        user: &User,
+    ) -> Result<impl Iterator<Item = globwalk::DirEntry>> {
+        let mut excludes: Vec<String> = vec![glob_pattern()];
         excludes.extend(
             get_review_scores_as_str()
                 .iter()
-                .map(|f| format!("!**/{f}/")),
+                .chain([ReviewScore::already_reviewed().as_str(), STATE_DIR_NAME].iter())
+                // only the buckets at the top of the media root, like the watcher
+                .map(|f| format!("!/{f}/")),
         );
 
-        GlobWalkerBuilder::from_patterns(self.root_dir.as_str(), &excludes)
-            .build()?
-            .filter_map(Result::ok)
-            .find_map(|img| {
+        Ok(
+            GlobWalkerBuilder::from_patterns(self.root_dir.as_str(), &excludes)
+                .case_insensitive(true)
+                .build()?
+                .filter_map(Result::ok)
+                // the glob only preselects by extension, photos are detected like in
+                // list_folder_images
+                .filter(|img| MediaFormat::detect_photo(img.path()).is_some())
+                .filter(|img| !self.is_deferred(img.path()))
+                .filter(|img| {
+                    user.can_access(
+                        Path::new(&self.to_relative_path(&img.path().to_string_lossy())),
+                        &self.origins,
+                    )
+                }),
+        )
+    }
+
+    // validates that the folder, relative to the media root, exists and is not a review bucket
+    fn resolve_folder_to_review(&self, folder: &str) -> Result<String> {
+        let relative_path = Path::new(folder.trim_start_matches('/'));
+        if relative_path
+            .components()
+            .any(|c| !matches!(c, Component::Normal(_)))
+        {
+            bail!("Invalid folder '{folder}'");
+        }
+        let is_excluded = relative_path
+            .components()
+            .next()
+            .and_then(|c| c.as_os_str().to_str())
+            .is_none_or(is_excluded_folder_name);
+        if is_excluded {
+            bail!("Folder '{folder}' cannot be reviewed");
+        }
+        let full_path = PathBuf::from(&self.root_dir).join(relative_path);
+        if !full_path.is_dir() {
+            bail!("Folder '{folder}' not found");
+        }
+        Ok(full_path.to_string_lossy().into())
+    }
+
+    fn find_next_folder_path_with_images_to_review(&self, user: &User) -> Result<String> {
+        let next_folder = match &self.pending_folders {
+            // the view lags behind by the debounce period and does not know about deferred
+            // photos, so the folder is checked before it is returned
+            Some(pending_folders) => pending_folders.folders().into_iter().find_map(|folder| {
+                let folder = folder.to_str()?;
+                if !user.can_access(Path::new(&self.to_relative_path(folder)), &self.origins) {
+                    return None;
+                }
+                list_folder_images(folder)
+                    .ok()?
+                    .iter()
+                    .any(|img| !self.is_deferred(img))
+                    .then(|| folder.into())
+            }),
+            None => self.walk_images_to_review(user)?.find_map(|img| {
                 img.path()
                     .parent()
                     .and_then(|p| p.to_str().map(std::convert::Into::into))
+            }),
+        };
+        next_folder.ok_or_else(|| {
+            anyhow!(
+                "No folders with images to review found under root folder {}",
+                self.root_dir
+            )
+        })
+    }
+}
+
+// with a voting rule, photos are reviewed by vote and only admins can decide on their own
+fn review_role() -> Role {
+    if VotingRule::configured().is_some() {
+        Role::Admin
+    } else {
+        Role::Reviewer
+    }
+}
+
+// admins may undo any review, others only their own
+fn may_undo(user: &User, performed: &PerformedMove) -> bool {
+    user.role == Role::Admin || performed.reviewer.as_ref() == Some(&user.name)
+}
+
+// the reviews that undo_last and redo apply to, which includes reviews of unknown reviewers for
+// admins
+fn is_own_review(user: &User, performed: &PerformedMove) -> bool {
+    performed
+        .reviewer
+        .as_ref()
+        .map_or(user.role == Role::Admin, |reviewer| *reviewer == user.name)
+}
+
+/// Whether folders with this name hold reviewed photos or the state of photomanager, instead of
+/// photos to review
+pub(crate) fn is_excluded_folder_name(name: &str) -> bool {
+    name == STATE_DIR_NAME
+        || name == ReviewScore::already_reviewed().as_str()
+        || get_review_scores_as_str().contains(&name)
+}
+
+// the number of photos left to review in the folder, which leaves out the deferred photos
+fn queue_count(root_dir: &str, folder: &Path, deferred: &Mutex<DeferredPhotos>) -> usize {
+    let Some(images) = folder.to_str().and_then(|f| list_folder_images(f).ok()) else {
+        return 0;
+    };
+    let Ok(deferred) = deferred.lock() else {
+        return images.len();
+    };
+    images
+        .iter()
+        .filter(|img| {
+            let relative_path = img.strip_prefix(root_dir).unwrap_or(img);
+            !deferred.contains(&relative_path.to_string_lossy())
+        })
+        .count()
+}
+
+// the photos in the folder, without the RAW files that are reviewed with their JPEG
+pub(crate) fn list_folder_images(folder: &str) -> Result<Vec<PathBuf>> {
+    Ok(fs::read_dir(folder)?
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.is_file() && MediaFormat::detect_photo(path).is_some() && !is_raw_companion(path)
+        })
+        .collect())
+}
+
+// the companion files of the photo at `from`, with the path that they get next to the photo at
+// `to` and whether a file is there already
+fn companion_moves(from: &str, to: &str) -> Vec<MovedFile> {
+    let (from, to) = (Path::new(from), Path::new(to));
+    find_companions(from)
+        .into_iter()
+        .filter_map(|companion| {
+            let destination = to.with_file_name(companion_name(&companion, from, to)?);
+            Some(MovedFile {
+                replaced: destination.exists(),
+                destination: destination.to_str()?.into(),
+                source: companion.to_str()?.into(),
             })
-            .ok_or_else(|| {
-                anyhow!(
-                    "No folders with images to review found under root folder {}",
-                    self.root_dir
-                )
-            })
+        })
+        .collect()
+}
+
+// the photos in the review buckets by their size
+#[derive(Default)]
+struct ReviewedContents {
+    sizes: HashMap<String, u64>,
+    paths_by_size: HashMap<u64, BTreeSet<String>>,
+}
+
+impl ReviewedContents {
+    fn add(&mut self, path: &str, size: u64) {
+        self.remove(path);
+        self.sizes.insert(path.into(), size);
+        self.paths_by_size
+            .entry(size)
+            .or_default()
+            .insert(path.into());
+    }
+
+    fn insert(&mut self, path: &str) {
+        match fs::metadata(path) {
+            Ok(metadata) => self.add(path, metadata.len()),
+            Err(e) => error!("Failed to read the size of reviewed photo {}: {}", path, e),
+        }
+    }
+
+    fn remove(&mut self, path: &str) {
+        if let Some(size) = self.sizes.remove(path)
+            && let Some(paths) = self.paths_by_size.get_mut(&size)
+        {
+            paths.remove(path);
+            if paths.is_empty() {
+                self.paths_by_size.remove(&size);
+            }
+        }
+    }
+
+    // only hashes the files when a reviewed photo with the same size exists
+    fn contains(&self, file_path: &str, hash_index: &HashIndex) -> bool {
+        let Some(candidates) = fs::metadata(file_path)
+            .ok()
+            .and_then(|m| self.paths_by_size.get(&m.len()))
+        else {
+            return false;
+        };
+        let Ok(hash) = hash_index.hash(file_path) else {
+            return false;
+        };
+        candidates
+            .iter()
+            .any(|candidate| hash_index.hash(candidate).is_ok_and(|h| h == hash))
     }
 }
@@ -0,0 +1,312 @@
+use crate::events::{Event, Events, NewPhotosArrived, QueueCountChanged};
+use crate::file_management::{is_excluded_folder_name, list_folder_images};
+use anyhow::Result;
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::sync::{Arc, Mutex, PoisonError, Weak};
+use std::thread;
+use std::time::Duration;
+use tracing::{error, info};
+
+const DEFAULT_DEBOUNCE_MS: u64 = 2000;
+
+/// The folders under the media root with photos to review, kept up to date by watching the media
+/// root for changes instead of walking it on every request. Changes are applied once the folder
+/// has been quiet for the debounce period, so that a sync app writing hundreds of photos causes a
+/// single rescan. Review buckets and the state folder are not watched, so reviews cause no
+/// rescans. Folders that gained photos are published as `NewPhotosArrived` events, followed by
+/// their `QueueCountChanged`.
+pub struct PendingFolders {
+    // the number of photos to review by folder
+    folders: Arc<Mutex<BTreeMap<PathBuf, usize>>>,
+    // the events stop when the watcher is dropped, which ends the debounce thread. The thread
+    // only holds a weak reference, to watch the folders that are added to the media root.
+    _watcher: Arc<Mutex<RecommendedWatcher>>,
+}
+
+impl PendingFolders {
+    /// Scans the media root and starts watching it. `queue_count` counts the photos left to
+    /// review in a folder.
+    pub fn watch(
+        media_path: &str,
+        debounce: Duration,
+        events: Events,
+        queue_count: impl Fn(&Path) -> usize + Send + 'static,
+    ) -> Result<Self> {
+        let root_dir = PathBuf::from(media_path);
+        let (sender, receiver) = mpsc::channel();
+        let mut watcher =
+            notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+                match event {
+                    Ok(event) => {
+                        let paths = if event.need_rescan() {
+                            vec![]
+                        } else if matches!(event.kind, EventKind::Access(_)) {
+                            return;
+                        } else {
+                            event.paths
+                        };
+                        // an empty list rescans everything
+                        let _ = sender.send(paths);
+                    }
+                    Err(e) => error!("Failed to watch the media root: {e}"),
+                }
+            })?;
+        watch_top_folders(&mut watcher, &root_dir)?;
+        let watcher = Arc::new(Mutex::new(watcher));
+        let weak_watcher = Arc::downgrade(&watcher);
+
+        let mut initial = BTreeMap::new();
+        // the photos that are already there are not new
+        Scan {
+            root_dir: &root_dir,
+            folders: &mut initial,
+            arrived: vec![],
+        }
+        .tree(&root_dir);
+        info!(
+            "Watching {} with {} folders to review",
+            root_dir.display(),
+            initial.len()
+        );
+        let folders = Arc::new(Mutex::new(initial));
+        let updated = Arc::clone(&folders);
+        thread::Builder::new()
+            .name("folder-watcher".into())
+            .spawn(move || {
+                debounce_changes(
+                    &root_dir,
+                    &weak_watcher,
+                    &receiver,
+                    debounce,
+                    &updated,
+                    &events,
+                    queue_count,
+                );
+            })?;
+        Ok(Self {
+            folders,
+            _watcher: watcher,
+        })
+    }
+
+    /// The debounce period is read from `WATCH_DEBOUNCE_MS`
+    pub fn from_env(
+        media_path: &str,
+        events: Events,
+        queue_count: impl Fn(&Path) -> usize + Send + 'static,
+    ) -> Result<Self> {
+        let debounce_ms = std::env::var("WATCH_DEBOUNCE_MS")
+            .ok()
+            .and_then(|ms| ms.parse().ok())
+            .unwrap_or(DEFAULT_DEBOUNCE_MS);
+        Self::watch(
+            media_path,
+            Duration::from_millis(debounce_ms),
+            events,
+            queue_count,
+        )
+    }
+
+    /// The full paths of the folders with photos to review, ordered by path. Changes of the last
+    /// debounce period are not included yet.
+    pub fn folders(&self) -> Vec<PathBuf> {
+        self.folders
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .keys()
+            .cloned()
+            .collect()
+    }
+}
+
+// collects the changed paths until no change arrives for the debounce period, then rescans the
+// folders that they are in
+fn debounce_changes(
+    root_dir: &Path,
+    watcher: &Weak<Mutex<RecommendedWatcher>>,
+    receiver: &Receiver<Vec<PathBuf>>,
+    debounce: Duration,
+    folders: &Mutex<BTreeMap<PathBuf, usize>>,
+    events: &Events,
+    queue_count: impl Fn(&Path) -> usize,
+) {
+    while let Ok(paths) = receiver.recv() {
+        let mut changed = BTreeSet::new();
+        let mut rescan_all = paths.is_empty();
+        changed.extend(paths);
+        loop {
+            match receiver.recv_timeout(debounce) {
+                Ok(paths) => {
+                    rescan_all |= paths.is_empty();
+                    changed.extend(paths);
+                }
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => return,
+            }
+        }
+        if let Some(watcher) = watcher.upgrade() {
+            let mut watcher = watcher.lock().unwrap_or_else(PoisonError::into_inner);
+            if rescan_all {
+                if let Err(e) = watch_top_folders(&mut watcher, root_dir) {
+                    error!("Failed to watch {}: {e}", root_dir.display());
+                }
+            } else {
+                // folders at the top of the media root that were added or removed
+                let top_folders = changed
+                    .iter()
+                    .filter(|path| path.parent() == Some(root_dir) && is_watched(root_dir, path));
+                for path in top_folders {
+                    if path.is_dir() {
+                        watch_top_folder(&mut watcher, path);
+                    } else if !path.exists() {
+                        // fails for files, which are not watched by themselves
+                        let _ = watcher.unwatch(path);
+                    }
+                }
+            }
+        }
+        // the folders are scanned into a copy, so that requests are not blocked by the scan. This
+        // thread is the only one that changes the folders.
+        let mut updated = folders
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone();
+        let mut scan = Scan {
+            root_dir,
+            folders: &mut updated,
+            arrived: vec![],
+        };
+        if rescan_all {
+            scan.tree(root_dir);
+        } else {
+            scan.changed(changed);
+        }
+        let arrived = scan.arrived;
+        *folders.lock().unwrap_or_else(PoisonError::into_inner) = updated;
+        for arrived in arrived {
+            let count = queue_count(&root_dir.join(&arrived.folder));
+            let folder = arrived.folder.clone();
+            events.publish(Event::NewPhotosArrived(arrived));
+            events.publish(Event::QueueCountChanged(QueueCountChanged {
+                folder,
+                count,
+            }));
+        }
+    }
+}
+
+struct Scan<'a> {
+    root_dir: &'a Path,
+    folders: &'a mut BTreeMap<PathBuf, usize>,
+    // the folders that gained photos, published once the scan is applied
+    arrived: Vec<NewPhotosArrived>,
+}
+
+impl Scan<'_> {
+    // rescans the folders that the changed paths are in
+    fn changed(&mut self, changed: BTreeSet<PathBuf>) {
+        let root_dir = self.root_dir;
+        for path in changed {
+            if !is_watched(root_dir, &path) {
+                continue;
+            }
+            if path.is_dir() {
+                // a folder that was created or moved in, possibly with photos already inside
+                self.tree(&path);
+            } else {
+                if !path.exists() {
+                    // a folder that was removed or moved out
+                    self.folders.retain(|folder, _| !folder.starts_with(&path));
+                }
+                if let Some(folder) = path.parent()
+                    && is_watched(root_dir, folder)
+                {
+                    self.folder(folder);
+                }
+            }
+        }
+    }
+
+    // rescans the folder and the folders below it
+    fn tree(&mut self, folder: &Path) {
+        let mut scanned = BTreeSet::new();
+        self.tree_folders(folder, &mut scanned);
+        // folders that no longer exist
+        self.folders
+            .retain(|f, _| !f.starts_with(folder) || scanned.contains(f));
+    }
+
+    fn tree_folders(&mut self, folder: &Path, scanned: &mut BTreeSet<PathBuf>) {
+        self.folder(folder);
+        scanned.insert(folder.into());
+        let Ok(entries) = fs::read_dir(folder) else {
+            return;
+        };
+        for path in entries.filter_map(Result::ok).map(|entry| entry.path()) {
+            if path.is_dir() && is_watched(self.root_dir, &path) {
+                self.tree_folders(&path, scanned);
+            }
+        }
+    }
+
+    fn folder(&mut self, folder: &Path) {
+        let count = folder
+            .to_str()
+            .and_then(|f| list_folder_images(f).ok())
+            .map_or(0, |images| images.len());
+        let previous = if count == 0 {
+            self.folders.remove(folder)
+        } else {
+            self.folders.insert(folder.into(), count)
+        };
+        let added = count.saturating_sub(previous.unwrap_or(0));
+        if added > 0 {
+            self.arrived.push(NewPhotosArrived {
+                folder: folder
+                    .strip_prefix(self.root_dir)
+                    .unwrap_or(folder)
+                    .to_string_lossy()
+                    .into(),
+                count: added,
+            });
+        }
+    }
+}
+
+// the media root is watched without its subfolders, so that the review buckets and the state
+// folder, which change with every review, cause no events. The other folders at the top of the
+// media root are watched with their subfolders.
+fn watch_top_folders(watcher: &mut RecommendedWatcher, root_dir: &Path) -> Result<()> {
+    watcher.watch(root_dir, RecursiveMode::NonRecursive)?;
+    for path in fs::read_dir(root_dir)?
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+    {
+        if path.is_dir() && is_watched(root_dir, &path) {
+            watch_top_folder(watcher, &path);
+        }
+    }
+    Ok(())
+}
+
+fn watch_top_folder(watcher: &mut RecommendedWatcher, folder: &Path) {
+    if let Err(e) = watcher.watch(folder, RecursiveMode::Recursive) {
+        error!("Failed to watch {}: {e}", folder.display());
+    }
+}
+
+// whether the path is under the media root and outside the review buckets and the state folder,
+// which are at the top of the media root. Folders deeper down are watched whatever their name.
+fn is_watched(root_dir: &Path, path: &Path) -> bool {
+    path.strip_prefix(root_dir).is_ok_and(|relative| {
+        relative
+            .components()
+            .next()
+            .and_then(|c| c.as_os_str().to_str())
+            .is_none_or(|name| !is_excluded_folder_name(name))
+    })
+}
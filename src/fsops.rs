@@ -1,21 +1,19 @@
+use crate::hash_index::HashIndex;
 use anyhow::{Context, Result, anyhow};
 use std::fs;
 use std::os::unix::fs::PermissionsExt;
 use std::path::{Path, PathBuf};
 use tracing::info;
 
-pub fn can_safely_overwrite(source: &str, destination: &str) -> Result<bool> {
+pub(crate) fn can_safely_overwrite(
+    source: &str,
+    destination: &str,
+    hash_index: &HashIndex,
+) -> Result<bool> {
     if !PathBuf::from(destination).exists() {
         return Ok(true);
     }
-    Ok(fs::read(source)? == fs::read(destination)?)
-}
-
-pub fn have_equal_contents(source: &str, destination: &str) -> Result<bool> {
-    if !PathBuf::from(destination).exists() {
-        return Ok(false);
-    }
-    Ok(fs::read(source)? == fs::read(destination)?)
+    hash_index.have_equal_contents(source, destination)
 }
 
 pub fn rename_with_create_dir_all(source: &str, destination: &str, mode: u32) -> Result<()> {
@@ -36,30 +34,35 @@ pub fn rename_with_create_dir_all(source: &str, destination: &str, mode: u32) ->
 }
 
 pub fn get_unique_filepath(file_path: &str) -> Result<String> {
+    numbered_filepaths(file_path)?
+        .find(|path| !Path::new(path).exists())
+        .ok_or_else(|| anyhow!("Failed to find unique file path for: {}", file_path))
+}
+
+/// The paths with a `-N` suffix after the file stem that are tried for a unique file path, like
+/// `photo-1.jpg` and `photo-2.jpg` for `photo.jpg`
+pub fn numbered_filepaths(file_path: &str) -> Result<impl Iterator<Item = String>> {
     let path = Path::new(file_path);
     let dir = path
         .parent()
-        .ok_or_else(|| anyhow!("Failed to get parent dir"))?;
+        .ok_or_else(|| anyhow!("Failed to get parent dir"))?
+        .to_path_buf();
     let title = path
         .file_stem()
         .and_then(|p| p.to_str())
-        .ok_or_else(|| anyhow!("no file title"))?;
+        .ok_or_else(|| anyhow!("no file title"))?
+        .to_string();
 
     let ext = path
         .extension()
         .and_then(|p| p.to_str())
         .map_or_else(String::new, |s| String::from(".") + s);
 
-    (1..=1000)
-        .find_map(|i| {
-            let last_path_buf = dir.join(format!("{title}-{i}{ext}"));
-            if Path::new(&last_path_buf).exists() {
-                None
-            } else {
-                Some(last_path_buf.to_str().unwrap().into())
-            }
-        })
-        .ok_or_else(|| anyhow!("Failed to find unique file path for: {}", file_path))
+    Ok((1..=1000).map(move |i| {
+        dir.join(format!("{title}-{i}{ext}"))
+            .to_string_lossy()
+            .into_owned()
+    }))
 }
 
 pub fn chmod(file_path: &str, mode: u32) -> Result<()> {
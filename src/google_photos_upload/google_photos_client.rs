@@ -1,5 +1,7 @@
 use crate::google_photos_upload::album::get_album_id;
 use crate::image::PhotoReview as ReviewedPhoto;
+use crate::live_photo::find_motion_clip;
+use crate::media_format::MediaFormat;
 use crate::reqwops;
 use anyhow::{Context, Result, bail};
 // use oauth2::basic::BasicClient;
@@ -8,7 +10,7 @@ use anyhow::{Context, Result, bail};
 // use oauth2::{AuthUrl, ClientId, ClientSecret, TokenUrl};
 use reqwest::header::{AUTHORIZATION, CONTENT_TYPE};
 use serde_json::json;
-use std::path::PathBuf;
+use std::path::Path;
 use std::sync::Arc;
 use std::{env, fs};
 use tracing::{debug, info};
@@ -36,45 +38,39 @@ impl GooglePhotosClient {
             );
         }
 
-        let album_name = format!("001-best-{}", req.image.album_name);
+        let album_name = format!("{}-{}", req.score.as_str(), req.image.album_name);
         let album_id = get_album_id(
             &album_name,
             self.get_auth_headers()?,
             Arc::clone(&self.reqwest_client),
         )
         .await?;
-        let upload_token = self
-            .upload_image_bytes(&req.image.full_path)
-            .await
-            .with_context(|| "Failed to upload image to google photos")?;
-        self.batch_create_media(&upload_token, album_id.as_str())
+        let mime_type = MediaFormat::detect(Path::new(&req.image.full_path))
+            .with_context(|| format!("Mime type of [{}] is not supported", req.image.full_path))?
+            .mime_type();
+        let mut upload_tokens = vec![
+            self.upload_image_bytes(&req.image.full_path, mime_type)
+                .await
+                .with_context(|| "Failed to upload image to google photos")?,
+        ];
+        // the motion clip of a Live Photo is uploaded as a separate video in the same album
+        if let Some(clip) = find_motion_clip(Path::new(&req.image.full_path)) {
+            upload_tokens.push(
+                self.upload_image_bytes(clip.to_str().context("to_str failed")?, "video/quicktime")
+                    .await
+                    .with_context(|| "Failed to upload motion clip to google photos")?,
+            );
+        }
+        self.batch_create_media(&upload_tokens, album_id.as_str())
             .await
     }
-    async fn upload_image_bytes(&self, image_path: &str) -> Result<String> {
+    async fn upload_image_bytes(&self, image_path: &str, mime_type: &str) -> Result<String> {
         let img_bytes = fs::read(image_path)?;
 
         let mut headers = self.get_auth_headers()?;
         headers.insert(CONTENT_TYPE, "application/octet-stream".parse()?);
         headers.insert("X-Goog-Upload-Protocol", "raw".parse()?); //
         //
-        let mime_type = match PathBuf::from(image_path)
-            .extension()
-            .context("Failed to get file extension of image_path")?
-            .to_str()
-            .context("Failed to get file extension of image_path")?
-        {
-            "jpg" | "jpeg" => Ok("image/jpeg"),
-            "png" => Ok("image/png"),
-            "gif" => Ok("image/gif"),
-            "heic" => Ok("image/heic"),
-            "tiff" => Ok("image/tiff"),
-            "webp" => Ok("image/webp"),
-            other_ext => Err(format!(
-                "Mime type of exstension [{other_ext}] is not supported",
-            )),
-        }
-        .map_err(|e| anyhow::anyhow!(e))?;
-
         headers.insert("X-Goog-Upload-Content-Type", mime_type.parse().unwrap()); //
 
         let response = Arc::clone(&self.reqwest_client)
@@ -101,21 +97,22 @@ impl GooglePhotosClient {
         Ok(response_body.to_owned())
     }
 
-    async fn batch_create_media(&self, upload_token: &str, album_id: &str) -> Result<()> {
+    async fn batch_create_media(&self, upload_tokens: &[String], album_id: &str) -> Result<()> {
         let post_result = reqwops::post_json(
             "https://photoslibrary.googleapis.com/v1/mediaItems:batchCreate",
             self.get_auth_headers()?,
             Arc::clone(&self.reqwest_client),
             &json!({
                 "albumId": album_id,
-                "newMediaItems": [
-                    {
+                "newMediaItems": upload_tokens
+                    .iter()
+                    .map(|upload_token| json!({
                         "description": "test",
                         "simpleMediaItem": {
                             "uploadToken": upload_token
                         }
-                    }
-                ]
+                    }))
+                    .collect::<Vec<_>>()
             }),
         )
         .await?;
@@ -2,14 +2,15 @@ mod album;
 mod google_photos_client;
 
 use self::google_photos_client::{GooglePhotosClient, OauthSecrets};
+use crate::events::{Event, Events, UploadStatus, UploadStatusChanged};
 use crate::image::PhotoReview as ReviewedPhoto;
-use crate::reviewscore::ReviewScore;
+use crate::reviewscore::UploadTarget;
 use anyhow::Result;
 use std::sync::mpsc;
 use tracing::{error, info, instrument};
 
 struct UploadRequestContext {
-    sender: mpsc::Sender<ReviewedPhoto>,
+    sender: mpsc::Sender<(ReviewedPhoto, Events)>,
     enabled: bool,
 }
 
@@ -22,13 +23,14 @@ struct UploadRequestContext {
 // make sure that google upload errors become visible
 // perf: hashmap for known albums
 
-pub fn upload_best_photos(review: ReviewedPhoto) -> Result<()> {
-    if review.score != ReviewScore::Best {
+/// Queues the upload of the reviewed photo, the progress is published to the events
+pub fn upload_reviewed_photo(review: ReviewedPhoto, events: &Events) -> Result<()> {
+    if review.score.upload_target() != Some(UploadTarget::GooglePhotos) {
         return Ok(());
     }
     UPLOAD_REQUESTER.with(|ctx| {
         if ctx.enabled {
-            Ok(ctx.sender.send(review)?)
+            Ok(ctx.sender.send((review, events.clone()))?)
         } else {
             info!("Google photos upload is disabled because env vars are not set");
             Ok(())
@@ -41,7 +43,7 @@ thread_local! {
 }
 
 fn init_upload_requester() -> UploadRequestContext {
-    let (sender, receiver) = mpsc::channel::<ReviewedPhoto>();
+    let (sender, receiver) = mpsc::channel::<(ReviewedPhoto, Events)>();
     let oauth_secrets = OauthSecrets::from_env();
     let ctx = UploadRequestContext {
         enabled: oauth_secrets.is_valid,
@@ -54,16 +56,32 @@ fn init_upload_requester() -> UploadRequestContext {
 
     tokio::spawn(async move {
         let client = GooglePhotosClient::new(&oauth_secrets);
-        for req in receiver {
-            single_run_upload_photo(&req, &client).await;
+        for (req, events) in receiver {
+            single_run_upload_photo(&req, &client, &events).await;
         }
     });
     ctx
 }
 
-#[instrument]
-async fn single_run_upload_photo(req: &ReviewedPhoto, client: &GooglePhotosClient) {
-    if let Err(e) = client.upload_photo(req).await {
-        error!("Failed to upload photo to Google Photos: {}", e);
+#[instrument(skip(events))]
+async fn single_run_upload_photo(
+    req: &ReviewedPhoto,
+    client: &GooglePhotosClient,
+    events: &Events,
+) {
+    let publish = |status, message| {
+        events.publish(Event::UploadStatusChanged(UploadStatusChanged {
+            path: req.image.url(),
+            status,
+            message,
+        }));
+    };
+    publish(UploadStatus::Uploading, None);
+    match client.upload_photo(req).await {
+        Ok(()) => publish(UploadStatus::Uploaded, None),
+        Err(e) => {
+            error!("Failed to upload photo to Google Photos: {}", e);
+            publish(UploadStatus::Failed, Some(e.to_string()));
+        }
     }
 }
@@ -1,14 +1,17 @@
+use crate::auth::{Authenticator, User, require_auth};
 use crate::model::{ServiceSchema, new_schema};
+use async_graphql::Data;
 use async_graphql::http::{ALL_WEBSOCKET_PROTOCOLS, GraphQLPlaygroundConfig, playground_source};
 use async_graphql_axum::{GraphQLProtocol, GraphQLRequest, GraphQLResponse, GraphQLWebSocket};
 use axum::{
     Router,
     extract::Extension,
-    http::HeaderMap,
+    middleware,
     response::{Html, IntoResponse, Response},
-    routing::get,
+    routing::{get, post},
 };
 use std::env;
+use std::sync::Arc;
 use tracing::info;
 
 async fn graphql_playground() -> impl IntoResponse {
@@ -20,14 +23,17 @@ async fn graphql_playground() -> impl IntoResponse {
 // #[debug_handler]
 async fn graphql_handler(
     schema: Extension<ServiceSchema>,
-    _headers: HeaderMap,
+    Extension(user): Extension<User>,
     req: GraphQLRequest,
 ) -> GraphQLResponse {
-    schema.execute(req.into_inner()).await.into()
+    schema.execute(req.into_inner().data(user)).await.into()
 }
 
+// browsers cannot set headers on websockets, so the token is taken from the connection_init
+// payload instead of being checked by the middleware
 async fn graphql_ws_handler(
     Extension(schema): Extension<ServiceSchema>,
+    Extension(authenticator): Extension<Arc<Authenticator>>,
     protocol: GraphQLProtocol,
     websocket: axum::extract::ws::WebSocketUpgrade,
 ) -> Response {
@@ -35,13 +41,22 @@ async fn graphql_ws_handler(
         .protocols(ALL_WEBSOCKET_PROTOCOLS)
         .on_upgrade(move |stream| {
             GraphQLWebSocket::new(stream, schema, protocol)
-                // for adding token-from-header support, see https://github.com/async-graphql/examples/tree/master/models/token
-                // .on_connection_init(on_connection_init)
+                .on_connection_init(move |payload| async move {
+                    let user = authenticator
+                        .authenticate_connection(&payload)
+                        .ok_or_else(|| async_graphql::Error::new("Unauthorized"))?;
+                    let mut data = Data::default();
+                    data.insert(user);
+                    Ok(data)
+                })
                 .serve()
         })
 }
 
-pub(crate) async fn run_graphql_server(router: Router) -> Router {
+pub(crate) async fn run_graphql_server(
+    router: Router,
+    authenticator: Arc<Authenticator>,
+) -> Router {
     // async-graphql-examples
     // https://github.com/async-graphql/examples
     info!(
@@ -49,7 +64,13 @@ pub(crate) async fn run_graphql_server(router: Router) -> Router {
         env::var("PUBLIC_URL").expect("'PUBLIC_URL' environment variable is required"),
     );
     router
-        .route("/graphql", get(graphql_playground).post(graphql_handler))
+        .route(
+            "/graphql",
+            get(graphql_playground).merge(post(graphql_handler).route_layer(
+                middleware::from_fn_with_state(Arc::clone(&authenticator), require_auth),
+            )),
+        )
         .route("/ws", get(graphql_ws_handler))
         .layer(Extension(new_schema(None)))
+        .layer(Extension(authenticator))
 }
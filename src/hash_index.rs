@@ -0,0 +1,255 @@
+use crate::metadata::capture_instant;
+use crate::perceptual_hash::dhash;
+use anyhow::{Context, Result, anyhow};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, MutexGuard};
+use std::time::UNIX_EPOCH;
+use tracing::{debug, error};
+
+const HASH_INDEX_FILE_NAME: &str = "hash_index.json";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct HashEntry {
+    size: u64,
+    mtime_nanos: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    hash: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    perceptual_hash: Option<PerceptualHash>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    capture_time: Option<CaptureTime>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+enum PerceptualHash {
+    DHash(u64),
+    // remembered so that files that cannot be decoded are not decoded over and over again
+    Undecodable,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+enum CaptureTime {
+    At(DateTime<Utc>),
+    // remembered so that files without EXIF capture time are not parsed over and over again
+    Unknown,
+}
+
+impl HashEntry {
+    const fn new(size: u64, mtime_nanos: u64) -> Self {
+        Self {
+            size,
+            mtime_nanos,
+            hash: None,
+            perceptual_hash: None,
+            capture_time: None,
+        }
+    }
+}
+
+/// Persisted index of BLAKE3 content hashes, perceptual hashes and EXIF capture times, keyed by
+/// file path. An entry is only reused as long as the size and modification time of the file are
+/// unchanged.
+pub struct HashIndex {
+    path: PathBuf,
+    entries: Mutex<HashMap<String, HashEntry>>,
+    // whether the entries changed since they were saved
+    dirty: AtomicBool,
+}
+
+impl HashIndex {
+    pub fn load(state_dir: &Path) -> Self {
+        let path = state_dir.join(HASH_INDEX_FILE_NAME);
+        let entries = Self::read(&path).unwrap_or_else(|e| {
+            error!("Failed to load the hash index, starting with an empty index: {e:#}");
+            HashMap::new()
+        });
+        Self {
+            path,
+            entries: Mutex::new(entries),
+            dirty: AtomicBool::new(false),
+        }
+    }
+
+    fn read(path: &Path) -> Result<HashMap<String, HashEntry>> {
+        if !path.exists() {
+            return Ok(HashMap::new());
+        }
+        serde_json::from_slice(&fs::read(path)?)
+            .with_context(|| format!("Failed to parse hash index '{}'", path.display()))
+    }
+
+    /// Returns the content hash of the file, only reading the file when it is not indexed yet or
+    /// when it has changed since it was indexed
+    pub fn hash(&self, file_path: &str) -> Result<String> {
+        let (size, mtime_nanos) = Self::size_and_mtime(file_path)?;
+        if let Some(hash) = self
+            .get_entry(file_path, size, mtime_nanos)?
+            .and_then(|e| e.hash)
+        {
+            return Ok(hash);
+        }
+
+        let mut hasher = blake3::Hasher::new();
+        hasher
+            .update_reader(fs::File::open(file_path)?)
+            .with_context(|| format!("Failed to hash {file_path}"))?;
+        let hash = hasher.finalize().to_hex().to_string();
+
+        self.update_entry(file_path, size, mtime_nanos, |e| {
+            e.hash = Some(hash.clone());
+        })?;
+        Ok(hash)
+    }
+
+    /// Returns the dHash of the image, or None when the file cannot be decoded as an image
+    pub fn perceptual_hash(&self, file_path: &str) -> Result<Option<u64>> {
+        let (size, mtime_nanos) = Self::size_and_mtime(file_path)?;
+        let perceptual_hash = match self
+            .get_entry(file_path, size, mtime_nanos)?
+            .and_then(|e| e.perceptual_hash)
+        {
+            Some(perceptual_hash) => perceptual_hash,
+            None => {
+                let perceptual_hash = dhash(file_path).map_or_else(
+                    |e| {
+                        debug!("No perceptual hash for {}: {:#}", file_path, e);
+                        PerceptualHash::Undecodable
+                    },
+                    PerceptualHash::DHash,
+                );
+                self.update_entry(file_path, size, mtime_nanos, |e| {
+                    e.perceptual_hash = Some(perceptual_hash);
+                })?;
+                perceptual_hash
+            }
+        };
+        Ok(match perceptual_hash {
+            PerceptualHash::DHash(hash) => Some(hash),
+            PerceptualHash::Undecodable => None,
+        })
+    }
+
+    /// Returns the EXIF capture time of the photo, only parsing the file when it is not indexed yet
+    /// or when it has changed since it was indexed
+    pub fn capture_time(&self, file_path: &str) -> Result<Option<DateTime<Utc>>> {
+        let (size, mtime_nanos) = Self::size_and_mtime(file_path)?;
+        let capture_time = match self
+            .get_entry(file_path, size, mtime_nanos)?
+            .and_then(|e| e.capture_time)
+        {
+            Some(capture_time) => capture_time,
+            None => {
+                let capture_time =
+                    capture_instant(file_path).map_or(CaptureTime::Unknown, CaptureTime::At);
+                self.update_entry(file_path, size, mtime_nanos, |e| {
+                    e.capture_time = Some(capture_time);
+                })?;
+                capture_time
+            }
+        };
+        Ok(match capture_time {
+            CaptureTime::At(time) => Some(time),
+            CaptureTime::Unknown => None,
+        })
+    }
+
+    // returns the entry of the file if it is still up to date
+    fn get_entry(&self, file_path: &str, size: u64, mtime_nanos: u64) -> Result<Option<HashEntry>> {
+        Ok(self
+            .lock_entries()?
+            .get(file_path)
+            .filter(|e| e.size == size && e.mtime_nanos == mtime_nanos)
+            .cloned())
+    }
+
+    fn update_entry(
+        &self,
+        file_path: &str,
+        size: u64,
+        mtime_nanos: u64,
+        update: impl FnOnce(&mut HashEntry),
+    ) -> Result<()> {
+        let mut entries = self.lock_entries()?;
+        let entry = entries
+            .entry(file_path.into())
+            .and_modify(|e| {
+                if e.size != size || e.mtime_nanos != mtime_nanos {
+                    *e = HashEntry::new(size, mtime_nanos);
+                }
+            })
+            .or_insert_with(|| HashEntry::new(size, mtime_nanos));
+        update(entry);
+        self.dirty.store(true, Ordering::Relaxed);
+        Ok(())
+    }
+
+    pub fn have_equal_contents(&self, source: &str, destination: &str) -> Result<bool> {
+        if fs::metadata(source)?.len() != fs::metadata(destination)?.len() {
+            return Ok(false);
+        }
+        Ok(self.hash(source)? == self.hash(destination)?)
+    }
+
+    /// Keeps the entry of a file that has been renamed, renaming preserves size and mtime
+    pub fn moved(&self, source: &str, destination: &str) {
+        if let Ok(mut entries) = self.lock_entries()
+            && let Some(entry) = entries.remove(source)
+        {
+            entries.insert(destination.into(), entry);
+            self.dirty.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// Removes the entries of the files that a walk found to be removed
+    pub fn prune(&self, is_removed: impl Fn(&Path) -> bool) {
+        if let Ok(mut entries) = self.lock_entries() {
+            let count = entries.len();
+            entries.retain(|path, _| !is_removed(Path::new(path)));
+            if entries.len() != count {
+                self.dirty.store(true, Ordering::Relaxed);
+            }
+        }
+    }
+
+    // the index is a cache, so a failure to persist it is logged only. Nothing is written when
+    // the entries did not change.
+    pub fn save(&self) {
+        if !self.dirty.swap(false, Ordering::Relaxed) {
+            return;
+        }
+        if let Err(e) = self.write() {
+            self.dirty.store(true, Ordering::Relaxed);
+            error!("Failed to save the hash index: {e:#}");
+        }
+    }
+
+    fn write(&self) -> Result<()> {
+        let contents = serde_json::to_vec(&*self.lock_entries()?)?;
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&self.path, contents)
+            .with_context(|| format!("Failed to write hash index '{}'", self.path.display()))
+    }
+
+    fn size_and_mtime(file_path: &str) -> Result<(u64, u64)> {
+        let metadata = fs::metadata(file_path)?;
+        let mtime_nanos = metadata
+            .modified()?
+            .duration_since(UNIX_EPOCH)?
+            .as_nanos()
+            .try_into()?;
+        Ok((metadata.len(), mtime_nanos))
+    }
+
+    fn lock_entries(&self) -> Result<MutexGuard<'_, HashMap<String, HashEntry>>> {
+        self.entries
+            .lock()
+            .map_err(|e| anyhow!("hash index lock poisoned: {e}"))
+    }
+}
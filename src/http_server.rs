@@ -1,38 +1,51 @@
+use crate::auth::{Authenticator, User, redact_token, require_auth, require_folder_access};
+use crate::file_management::STATE_DIR_NAME;
 use crate::graphql_server::run_graphql_server;
+use crate::journal::ReviewOrigins;
+use crate::preview::{PreviewCache, PreviewSize};
+use crate::rendition::warn_without_heif_converter;
+use crate::reviewscore::init_buckets;
+use crate::voting::init_voting_rule;
 use axum::Router;
-use axum::response::IntoResponse;
+use axum::extract::{Extension, Path as AxumPath, State};
+use axum::http::{StatusCode, header};
+use axum::middleware;
+use axum::response::{IntoResponse, Response};
 use axum::routing::get;
 // only import this as dev-dependency
 // #[cfg(debug_assertions)]
 use anyhow::Result;
 use listenfd::ListenFd;
 use std::env;
+use std::path::Path;
+use std::sync::Arc;
 use tokio::signal;
+use tower::ServiceBuilder;
 use tower_http::cors::CorsLayer;
 use tower_http::services::ServeDir;
 use tower_http::trace::TraceLayer;
-use tracing::info;
+use tracing::{Span, debug_span, error, info, warn};
 
 pub(crate) async fn run_http_server() -> Result<()> {
     info!("Starting HTTP server");
+    init_buckets()?;
+    warn_without_heif_converter();
     let media_root_dir: String = shellexpand::env(
         &env::var("MEDIA_ROOT").expect("'MEDIA_ROOT' environment variable is required"),
     )?
     .into();
 
-    let app = Router::new()
-        .nest_service("/media", ServeDir::new(media_root_dir))
-        .route("/healthz", get(liveness_handler))
-        .route("/readyz", get(ready_handler));
+    let authenticator = Arc::new(Authenticator::from_env()?);
+    init_voting_rule(&authenticator)?;
 
-    let app = app
+    let app = router(&media_root_dir, Arc::clone(&authenticator))
         .layer(CorsLayer::permissive())
-        .layer(TraceLayer::new_for_http());
+        .layer(TraceLayer::new_for_http().make_span_with(make_span));
 
-    let app = run_graphql_server(app)
+    let app = run_graphql_server(app, authenticator)
         .await
         .layer(CorsLayer::permissive())
-        .layer(TraceLayer::new_for_http());
+        .layer(TraceLayer::new_for_http().make_span_with(make_span));
 
     // listenfd keeps a socket open on the target port,
     // so that a request from the browser does not fail during compilation
@@ -55,6 +68,45 @@ pub(crate) async fn run_http_server() -> Result<()> {
     Ok(())
 }
 
+/// The photos under the media root at `/media` and their previews at `/preview`, for the users
+/// of the authenticator that have access to them, and the health checks
+pub fn router(media_root_dir: &str, authenticator: Arc<Authenticator>) -> Router {
+    let origins = Arc::new(ReviewOrigins::new(
+        &Path::new(media_root_dir).join(STATE_DIR_NAME),
+    ));
+    let previews = Router::new()
+        .route("/preview/{size}/{*path}", get(preview_handler))
+        .with_state((
+            Arc::new(PreviewCache::from_env(media_root_dir)),
+            Arc::clone(&origins),
+        ));
+
+    Router::new()
+        .nest_service(
+            "/media",
+            ServiceBuilder::new()
+                .layer(middleware::from_fn_with_state(
+                    origins,
+                    require_folder_access,
+                ))
+                .service(ServeDir::new(media_root_dir)),
+        )
+        .merge(previews)
+        .route_layer(middleware::from_fn_with_state(authenticator, require_auth))
+        .route("/healthz", get(liveness_handler))
+        .route("/readyz", get(ready_handler))
+}
+
+// like the default span of TraceLayer, without the token of the access_token query parameter
+fn make_span(request: &axum::extract::Request) -> Span {
+    debug_span!(
+        "request",
+        method = %request.method(),
+        uri = %redact_token(request.uri()),
+        version = ?request.version(),
+    )
+}
+
 #[allow(dead_code)]
 async fn shutdown_signal() {
     let ctrl_c = async {
@@ -82,6 +134,62 @@ async fn shutdown_signal() {
     info!("signal received, starting graceful shutdown");
 }
 
+async fn preview_handler(
+    State((cache, origins)): State<(Arc<PreviewCache>, Arc<ReviewOrigins>)>,
+    Extension(user): Extension<User>,
+    AxumPath((size, path)): AxumPath<(String, String)>,
+) -> Response {
+    if !user.can_access(Path::new(&path), &origins) {
+        return (StatusCode::FORBIDDEN, "Forbidden").into_response();
+    }
+    let Some(size) = PreviewSize::from_name(&size) else {
+        return (
+            StatusCode::NOT_FOUND,
+            format!("Unknown preview size '{size}'"),
+        )
+            .into_response();
+    };
+    // decoding and resizing is CPU bound, so it runs on the blocking thread pool
+    let result = tokio::task::spawn_blocking(move || {
+        cache
+            .get(&path, size)
+            .map(|preview| (preview, "image/jpeg"))
+            // photos that browsers can display are served as they are instead
+            .or_else(|e| match cache.original(&path) {
+                Some(original) => {
+                    warn!("Serving the original of {path}, no preview: {e:#}");
+                    Ok(original)
+                }
+                None => Err(e),
+            })
+    })
+    .await;
+    match result {
+        Ok(Ok((preview, content_type))) => match tokio::fs::read(&preview).await {
+            Ok(bytes) => (
+                [
+                    (header::CONTENT_TYPE, content_type),
+                    (header::CACHE_CONTROL, "private, max-age=86400"),
+                ],
+                bytes,
+            )
+                .into_response(),
+            Err(e) => {
+                error!("Failed to read preview {}: {}", preview.display(), e);
+                StatusCode::INTERNAL_SERVER_ERROR.into_response()
+            }
+        },
+        Ok(Err(e)) => {
+            error!("Failed to render preview: {:#}", e);
+            (StatusCode::NOT_FOUND, e.to_string()).into_response()
+        }
+        Err(e) => {
+            error!("Preview rendering panicked: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
 async fn ready_handler() -> impl IntoResponse {
     (axum::http::StatusCode::OK, "OK")
 }
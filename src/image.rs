@@ -1,6 +1,9 @@
 use crate::reviewscore::ReviewScore;
+use crate::voting::Vote;
 use anyhow::{Context, Result};
-use async_graphql::SimpleObject;
+use async_graphql::connection::PageInfo;
+use async_graphql::{Enum, InputObject, SimpleObject};
+use chrono::{DateTime, NaiveDateTime, Utc};
 use std::path::{Path, PathBuf};
 
 #[derive(Debug, Clone)]
@@ -15,17 +18,145 @@ impl PhotoReview {
     }
 }
 
+#[derive(InputObject)]
+pub struct PhotoReviewInput {
+    pub path: String,
+    pub score: ReviewScore,
+}
+
+#[derive(SimpleObject)]
+pub struct ReviewResults {
+    /// The result of every review, in the order of the input
+    pub items: Vec<ReviewResult>,
+}
+#[derive(SimpleObject)]
+pub struct FinishedFolder {
+    pub reviewed_count: usize,
+    /// Photos that could not be reviewed stay in the folder
+    pub failed_count: usize,
+    /// The result of every photo that was left in the folder
+    pub items: Vec<ReviewResult>,
+}
+#[derive(SimpleObject)]
+pub struct ReviewResult {
+    pub path: String,
+    pub success: bool,
+    /// The url of the reviewed photo, or the reason why it was not reviewed
+    pub output: String,
+}
+
 #[derive(SimpleObject)]
 pub struct PhotosToReview {
     pub base_url: String,
     pub photos: Vec<ImageToReview>,
+    /// The photos grouped into clusters of near-identical shots, like bursts
+    pub groups: Vec<PhotoGroup>,
+    pub page_info: PageInfo,
     pub folder_image_count: usize,
     pub folder_name: String,
 }
-#[derive(SimpleObject)]
+#[derive(SimpleObject, Clone)]
 pub struct ImageToReview {
+    /// Pass as `after` to fetch the photos that follow this photo
+    pub cursor: String,
+    /// The original, or a JPEG rendition for formats that browsers cannot display, like HEIC,
+    /// TIFF and RAW
     pub url: String,
+    /// Small JPEG rendition for grids and filmstrips
+    pub thumbnail_url: String,
+    /// JPEG rendition that fits a screen, to review without downloading the original
+    pub preview_url: String,
+    /// The RAW file that was shot together with the photo at `url`, reviewing the photo moves
+    /// both files
+    pub raw_url: Option<String>,
+    /// The motion clip of a Live Photo, which moves and uploads together with the photo
+    pub motion_url: Option<String>,
     pub album: String,
+    /// Star rating from the XMP sidecar
+    pub rating: Option<i32>,
+    /// Color label from the XMP sidecar
+    pub label: Option<String>,
+    /// Tags from the XMP sidecar
+    pub tags: Vec<String>,
+    /// The open votes on the photo when photos are reviewed by vote
+    pub votes: Vec<Vote>,
+    pub exif: ExifMetadata,
+}
+#[derive(SimpleObject, Clone, Default)]
+pub struct ExifMetadata {
+    /// The moment the shutter was pressed in the local time of the camera
+    pub capture_time: Option<NaiveDateTime>,
+    /// Offset of the local time of the camera from UTC, like +02:00
+    pub capture_time_offset: Option<String>,
+    pub make: Option<String>,
+    pub model: Option<String>,
+    pub lens: Option<String>,
+    /// Focal length in millimeters
+    pub focal_length: Option<f64>,
+    /// The f-number
+    pub aperture: Option<f64>,
+    /// Shutter speed in seconds, like 1/250
+    pub exposure_time: Option<String>,
+    pub iso: Option<u32>,
+    /// Decimal degrees, negative on the southern hemisphere
+    pub latitude: Option<f64>,
+    /// Decimal degrees, negative west of Greenwich
+    pub longitude: Option<f64>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    /// EXIF orientation, 1 is upright
+    pub orientation: Option<u32>,
+}
+#[derive(Enum, Copy, Clone, Default, Eq, PartialEq)]
+pub enum PhotoOrderField {
+    /// EXIF capture time, photos without capture time are ordered by modification time
+    CaptureTime,
+    ModifiedTime,
+    FileSize,
+    #[default]
+    FileName,
+}
+#[derive(Enum, Copy, Clone, Default, Eq, PartialEq)]
+pub enum OrderDirection {
+    #[default]
+    Asc,
+    Desc,
+}
+#[derive(InputObject, Copy, Clone, Default)]
+pub struct PhotoOrder {
+    #[graphql(default)]
+    pub field: PhotoOrderField,
+    #[graphql(default)]
+    pub direction: OrderDirection,
+}
+#[derive(SimpleObject)]
+pub struct FoldersToReview {
+    pub folders: Vec<FolderToReview>,
+}
+#[derive(SimpleObject)]
+pub struct FolderToReview {
+    /// Path relative to the media root, pass as `folder` to photosToReview
+    pub path: String,
+    pub name: String,
+    pub image_count: usize,
+    /// Modification time of the oldest image in the folder
+    pub oldest: DateTime<Utc>,
+    /// Modification time of the newest image in the folder
+    pub newest: DateTime<Utc>,
+}
+#[derive(SimpleObject)]
+pub struct DeferredPhotoList {
+    pub photos: Vec<DeferredPhoto>,
+}
+#[derive(SimpleObject)]
+pub struct DeferredPhoto {
+    pub url: String,
+    pub album: String,
+    pub deferred_at: DateTime<Utc>,
+}
+#[derive(SimpleObject)]
+pub struct PhotoGroup {
+    pub photos: Vec<ImageToReview>,
 }
 
 #[derive(Debug, Clone)]
@@ -34,6 +165,7 @@ pub struct Image {
     pub root_dir: String,
     pub full_path: String,
     pub album_name: String,
+    pub perceptual_hash: Option<u64>,
 }
 impl Image {
     pub fn try_new(relative_path: &str, root_dir: &str) -> Result<Self> {
@@ -55,6 +187,7 @@ impl Image {
                 .to_str()
                 .context("Failed to convert to str")?
                 .into(),
+            perceptual_hash: None,
         })
     }
     pub fn from_full_path(full_path: &str, root_dir: &str) -> Self {
@@ -75,8 +208,12 @@ impl Image {
                 .to_str()
                 .unwrap()
                 .into(),
+            perceptual_hash: None,
         }
     }
+    pub fn url(&self) -> String {
+        "/media/".to_string() + &self.relative_path
+    }
     // Returns <root_dir>/score/album/filename
     pub fn get_destination_path(&self, score: ReviewScore) -> String {
         PathBuf::from(&self.root_dir)
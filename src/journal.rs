@@ -0,0 +1,316 @@
+use crate::reviewscore::ReviewScore;
+use anyhow::{Context, Result};
+use async_graphql::{Enum, SimpleObject};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, PoisonError};
+use tracing::error;
+
+const JOURNAL_FILE_NAME: &str = "journal.jsonl";
+
+#[derive(Debug, Enum, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JournalAction {
+    Review,
+    Undo,
+    Redo,
+}
+
+/// A single review decision as it was applied to the file system. Paths are relative to the
+/// media root.
+#[derive(Debug, Clone, SimpleObject, Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub id: u64,
+    pub action: JournalAction,
+    pub source: String,
+    pub destination: String,
+    pub score: ReviewScore,
+    pub timestamp: DateTime<Utc>,
+}
+
+#[derive(SimpleObject)]
+pub struct ReviewHistory {
+    pub entries: Vec<JournalEntry>,
+    /// Pass as `cursor` to fetch the next (older) page, null when there are no older entries
+    pub next_cursor: Option<u64>,
+}
+
+/// Append-only JSONL journal of all review and undo operations
+pub struct Journal {
+    path: PathBuf,
+    last_id: Mutex<u64>,
+}
+
+impl Journal {
+    pub fn new(state_dir: &Path) -> Self {
+        let path = state_dir.join(JOURNAL_FILE_NAME);
+        let last_id = Self::read_entries_newest_first(&path)
+            .ok()
+            .and_then(|mut entries| entries.find_map(Result::ok))
+            .map(|e| e.id)
+            .unwrap_or_default();
+        Self {
+            path,
+            last_id: Mutex::new(last_id),
+        }
+    }
+
+    pub fn append(
+        &self,
+        action: JournalAction,
+        source: &str,
+        destination: &str,
+        score: ReviewScore,
+    ) -> Result<JournalEntry> {
+        let mut last_id = self
+            .last_id
+            .lock()
+            .map_err(|e| anyhow::anyhow!("journal lock poisoned: {e}"))?;
+
+        let entry = JournalEntry {
+            id: *last_id + 1,
+            action,
+            source: source.into(),
+            destination: destination.into(),
+            score,
+            timestamp: Utc::now(),
+        };
+
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent).with_context(|| {
+                format!("Failed to create journal folder '{}'", parent.display())
+            })?;
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .with_context(|| format!("Failed to open journal '{}'", self.path.display()))?;
+        writeln!(file, "{}", serde_json::to_string(&entry)?)?;
+        file.sync_data()?;
+
+        *last_id = entry.id;
+        Ok(entry)
+    }
+
+    /// Returns at most `limit` of the visible entries older than `cursor`, newest first
+    pub fn history(
+        &self,
+        limit: usize,
+        cursor: Option<u64>,
+        visible: impl Fn(&JournalEntry) -> bool,
+    ) -> Result<ReviewHistory> {
+        // the journal only grows, so it is read from the end up to the requested page
+        let mut entries = Self::read_entries_newest_first(&self.path)?
+            .filter(|e| {
+                e.as_ref()
+                    .map_or(true, |e| cursor.is_none_or(|c| e.id < c) && visible(e))
+            })
+            .take(limit.saturating_add(1))
+            .collect::<Result<Vec<_>>>()?;
+
+        let next_cursor = if entries.len() > limit {
+            entries.truncate(limit);
+            entries.last().map(|e| e.id)
+        } else {
+            None
+        };
+
+        Ok(ReviewHistory {
+            entries,
+            next_cursor,
+        })
+    }
+
+    fn read_entries_newest_first(
+        path: &Path,
+    ) -> Result<impl Iterator<Item = Result<JournalEntry>>> {
+        let lines = if path.exists() {
+            let file = fs::File::open(path)
+                .with_context(|| format!("Failed to open journal '{}'", path.display()))?;
+            Some(ReverseLines::new(file)?)
+        } else {
+            None
+        };
+        Ok(lines.into_iter().flatten().filter_map(|line| {
+            let line = match line {
+                Ok(line) => line,
+                Err(e) => return Some(Err(e.into())),
+            };
+            let line = String::from_utf8_lossy(&line);
+            if line.trim().is_empty() {
+                return None;
+            }
+            // e.g. entries of a review bucket that has been removed from the configuration
+            match serde_json::from_str::<JournalEntry>(&line) {
+                Ok(entry) => Some(Ok(entry)),
+                Err(e) => {
+                    error!("Skipping journal entry {}: {}", line, e);
+                    None
+                }
+            }
+        }))
+    }
+}
+
+/// The folders that the photos in the review buckets were reviewed from, as recorded in the
+/// journal. The journal is read incrementally whenever an origin is looked up, so that reviews
+/// by another instance on the same media root are known as well.
+pub struct ReviewOrigins {
+    path: PathBuf,
+    state: Mutex<OriginsState>,
+}
+
+#[derive(Default)]
+struct OriginsState {
+    // how far the journal has been read
+    offset: u64,
+    // the folder that a photo was reviewed from, by its path in the bucket
+    photos: HashMap<PathBuf, PathBuf>,
+    // the same by bucket folder and name of the photo without extensions, for the companion
+    // files that are moved along with a photo, like `IMG_0001.MOV` with `IMG_0001.JPG`
+    companions: HashMap<(PathBuf, String), PathBuf>,
+}
+
+impl ReviewOrigins {
+    pub fn new(state_dir: &Path) -> Self {
+        Self {
+            path: state_dir.join(JOURNAL_FILE_NAME),
+            state: Mutex::new(OriginsState::default()),
+        }
+    }
+
+    /// The folder relative to the media root that the photo at the path in a review bucket was
+    /// reviewed from, `None` when the journal does not know the photo
+    pub fn folder(&self, reviewed_path: &Path) -> Option<PathBuf> {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Err(e) = state.read_journal(&self.path) {
+            error!("Failed to read journal '{}': {:#}", self.path.display(), e);
+        }
+        state
+            .photos
+            .get(reviewed_path)
+            .or_else(|| state.companions.get(&companion_key(reviewed_path)?))
+            .cloned()
+    }
+}
+
+impl OriginsState {
+    fn read_journal(&mut self, path: &Path) -> Result<()> {
+        let mut file = match fs::File::open(path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                *self = Self::default();
+                return Ok(());
+            }
+            Err(e) => return Err(e.into()),
+        };
+        if file.metadata()?.len() < self.offset {
+            // the journal was replaced, e.g. by a backup
+            *self = Self::default();
+        }
+        file.seek(SeekFrom::Start(self.offset))?;
+        let mut appended = vec![];
+        file.read_to_end(&mut appended)?;
+        // an entry that is still being written is read the next time
+        let Some(end) = appended.iter().rposition(|b| *b == b'\n') else {
+            return Ok(());
+        };
+        for line in appended[..end].split(|b| *b == b'\n') {
+            let line = String::from_utf8_lossy(line);
+            if line.trim().is_empty() {
+                continue;
+            }
+            let Ok(entry) = serde_json::from_str::<JournalEntry>(&line) else {
+                continue;
+            };
+            match entry.action {
+                JournalAction::Review | JournalAction::Redo => {
+                    let destination = Path::new(&entry.destination);
+                    if let Some(folder) = Path::new(&entry.source).parent() {
+                        if let Some(key) = companion_key(destination) {
+                            self.companions.insert(key, folder.into());
+                        }
+                        self.photos.insert(destination.into(), folder.into());
+                    }
+                }
+                JournalAction::Undo => {
+                    let source = Path::new(&entry.source);
+                    if let Some(key) = companion_key(source) {
+                        self.companions.remove(&key);
+                    }
+                    self.photos.remove(source);
+                }
+            }
+        }
+        self.offset += end as u64 + 1;
+        Ok(())
+    }
+}
+
+fn companion_key(reviewed_path: &Path) -> Option<(PathBuf, String)> {
+    let name = reviewed_path.file_name()?.to_str()?;
+    let stem = name.split('.').next().unwrap_or(name);
+    Some((reviewed_path.parent()?.into(), stem.into()))
+}
+
+// the lines of a file from the last to the first, read in chunks from the end of the file
+struct ReverseLines {
+    file: fs::File,
+    // the start of the bytes in `pending` within the file
+    position: u64,
+    // the bytes of the lines that have been read but not yet returned
+    pending: Vec<u8>,
+}
+
+impl ReverseLines {
+    const CHUNK_SIZE: u64 = 64 * 1024;
+
+    fn new(mut file: fs::File) -> io::Result<Self> {
+        let position = file.seek(SeekFrom::End(0))?;
+        Ok(Self {
+            file,
+            position,
+            pending: vec![],
+        })
+    }
+}
+
+impl Iterator for ReverseLines {
+    type Item = io::Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(newline) = self.pending.iter().rposition(|b| *b == b'\n') {
+                let line = self.pending.split_off(newline + 1);
+                self.pending.truncate(newline);
+                if line.is_empty() {
+                    continue;
+                }
+                return Some(Ok(line));
+            }
+            if self.position == 0 {
+                return (!self.pending.is_empty()).then(|| Ok(std::mem::take(&mut self.pending)));
+            }
+            let len = self.position.min(Self::CHUNK_SIZE);
+            self.position -= len;
+            let mut chunk = vec![0; usize::try_from(len).expect("chunk fits in memory")];
+            if let Err(e) = self
+                .file
+                .seek(SeekFrom::Start(self.position))
+                .and_then(|_| self.file.read_exact(&mut chunk))
+            {
+                // the iterator ends after an error
+                self.position = 0;
+                self.pending.clear();
+                return Some(Err(e));
+            }
+            chunk.append(&mut self.pending);
+            self.pending = chunk;
+        }
+    }
+}
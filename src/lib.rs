@@ -1,18 +1,34 @@
+pub mod auth;
+mod deferred;
+pub mod events;
 mod file_management;
+pub mod folder_watcher;
 pub mod fsops;
 mod google_photos_upload;
 mod graphql_server;
-mod http_server;
+mod hash_index;
+pub mod http_server;
 mod image;
+pub mod journal;
+mod live_photo;
+mod media_format;
+mod metadata;
 pub mod model;
+mod perceptual_hash;
+pub mod preview;
+mod rendition;
 mod reqwops;
 pub mod reviewscore;
+mod sidecar;
+mod undo_stack;
+pub mod voting;
+mod xmp;
 use dotenvy::dotenv;
 
 pub async fn run_server() {
     dotenv().ok();
     console_subscriber::init();
     if let Err(e) = http_server::run_http_server().await {
-        tracing::error!("Failed to run the HTTP server: {e}");
+        tracing::error!("Failed to run the HTTP server: {e:#}");
     }
 }
@@ -0,0 +1,214 @@
+use crate::metadata::read_exif;
+use exif::{In, Tag, Value};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+const CONTENT_IDENTIFIER_KEY: &[u8] = b"com.apple.quicktime.content.identifier";
+// the moov box of a Live Photo clip is small, larger boxes are not read into memory
+const MAX_MOOV_SIZE: u64 = 16 * 1024 * 1024;
+
+/// The motion clip of an iPhone Live Photo: the MOV with the same file stem, or else the MOV in
+/// the same folder with the same Apple ContentIdentifier as the photo
+pub fn find_motion_clip(image_path: &Path) -> Option<PathBuf> {
+    MotionClips::default().find(image_path)
+}
+
+/// Finds the motion clips of the photos in a folder, reading the ContentIdentifier of each MOV
+/// in the folder only once instead of once per photo
+#[derive(Default)]
+pub struct MotionClips {
+    // the clips by ContentIdentifier, per folder
+    by_identifier: RefCell<HashMap<PathBuf, HashMap<String, PathBuf>>>,
+}
+
+impl MotionClips {
+    /// The motion clip of the photo, like `find_motion_clip`
+    pub fn find(&self, image_path: &Path) -> Option<PathBuf> {
+        let image_identifier = image_content_identifier(image_path);
+        let same_name = ["mov", "MOV"]
+            .into_iter()
+            .map(|e| image_path.with_extension(e))
+            .find(|p| p.is_file());
+        if let Some(clip) = same_name {
+            // a clip with the same name that belongs to a different photo is not a pair
+            let is_pair = image_identifier.as_ref().is_none_or(|identifier| {
+                clip_content_identifier(&clip)
+                    .is_none_or(|clip_identifier| clip_identifier == *identifier)
+            });
+            return is_pair.then_some(clip);
+        }
+
+        let image_identifier = image_identifier?;
+        let folder = image_path.parent()?;
+        self.by_identifier
+            .borrow_mut()
+            .entry(folder.into())
+            .or_insert_with(|| clips_by_identifier(folder))
+            .get(&image_identifier)
+            .cloned()
+    }
+}
+
+fn clips_by_identifier(folder: &Path) -> HashMap<String, PathBuf> {
+    fs::read_dir(folder).map_or_else(
+        |_| HashMap::new(),
+        |entries| {
+            entries
+                .filter_map(Result::ok)
+                .map(|entry| entry.path())
+                .filter(|p| {
+                    p.extension()
+                        .and_then(|e| e.to_str())
+                        .is_some_and(|e| e.eq_ignore_ascii_case("mov"))
+                })
+                .filter_map(|clip| Some((clip_content_identifier(&clip)?, clip)))
+                .collect()
+        },
+    )
+}
+
+/// The ContentIdentifier is tag 0x11 of the Apple maker note in the EXIF data of the photo
+fn image_content_identifier(image_path: &Path) -> Option<String> {
+    let exif = read_exif(image_path.to_str()?)?;
+    let field = exif.get_field(Tag::MakerNote, In::PRIMARY)?;
+    let Value::Undefined(ref maker_note, _) = field.value else {
+        return None;
+    };
+    apple_content_identifier(maker_note)
+}
+
+// the Apple maker note is an IFD that follows a 14 byte header, with offsets relative to the
+// start of the maker note
+fn apple_content_identifier(maker_note: &[u8]) -> Option<String> {
+    if !maker_note.starts_with(b"Apple iOS\0") {
+        return None;
+    }
+    let big_endian = maker_note.get(12..14)? == b"MM";
+    let u16_at = |offset: usize| -> Option<u16> {
+        let bytes = maker_note.get(offset..offset + 2)?.try_into().ok()?;
+        Some(if big_endian {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        })
+    };
+    let u32_at = |offset: usize| -> Option<usize> {
+        let bytes = maker_note.get(offset..offset + 4)?.try_into().ok()?;
+        let value = if big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        };
+        usize::try_from(value).ok()
+    };
+
+    (0..usize::from(u16_at(14)?)).find_map(|i| {
+        let entry = 16 + i * 12;
+        // an ASCII value with tag 0x11
+        if u16_at(entry)? != 0x11 || u16_at(entry + 2)? != 2 {
+            return None;
+        }
+        let len = u32_at(entry + 4)?;
+        let start = if len <= 4 {
+            entry + 8
+        } else {
+            u32_at(entry + 8)?
+        };
+        let value = maker_note.get(start..start + len)?;
+        Some(String::from_utf8_lossy(value).trim_end_matches('\0').into())
+    })
+}
+
+/// The ContentIdentifier is stored in the QuickTime metadata of the clip, as the `ilst` item
+/// whose index is the position of its key in the `keys` box
+fn clip_content_identifier(clip_path: &Path) -> Option<String> {
+    let moov = read_top_level_box(clip_path, b"moov")?;
+    let meta = find_box(&moov, b"meta")?;
+    // the QuickTime meta box starts with its children, the ISO one with version and flags
+    let meta = if meta.get(4..8) == Some(b"hdlr") {
+        meta
+    } else {
+        meta.get(4..)?
+    };
+    let keys = find_box(meta, b"keys")?;
+    let ilst = find_box(meta, b"ilst")?;
+
+    // version and flags, the entry count and then entries of size, namespace and key name
+    let mut offset = 8;
+    let mut index = 0u32;
+    let key_index = loop {
+        let size = usize::try_from(u32::from_be_bytes(
+            keys.get(offset..offset + 4)?.try_into().ok()?,
+        ))
+        .ok()?;
+        index += 1;
+        if keys.get(offset + 8..offset + size)? == CONTENT_IDENTIFIER_KEY {
+            break index;
+        }
+        offset += size.max(8);
+    };
+
+    // the items are boxes with the key index as type, holding a data box with a type indicator
+    // and locale before the value
+    let item = boxes(ilst).find(|(box_type, _)| u32::from_be_bytes(**box_type) == key_index)?;
+    let data = find_box(item.1, b"data")?;
+    Some(String::from_utf8_lossy(data.get(8..)?).into())
+}
+
+fn read_top_level_box(path: &Path, box_type: &[u8; 4]) -> Option<Vec<u8>> {
+    let mut file = File::open(path).ok()?;
+    let file_size = file.metadata().ok()?.len();
+    let mut position = 0u64;
+    while file_size.saturating_sub(position) >= 8 {
+        let mut header = [0u8; 8];
+        file.seek(SeekFrom::Start(position)).ok()?;
+        file.read_exact(&mut header).ok()?;
+        let (size, header_size) = match u32::from_be_bytes(header[..4].try_into().ok()?) {
+            0 => (file_size - position, 8),
+            1 => {
+                let mut large_size = [0u8; 8];
+                file.read_exact(&mut large_size).ok()?;
+                (u64::from_be_bytes(large_size), 16)
+            }
+            size => (u64::from(size), 8),
+        };
+        if size < header_size {
+            return None;
+        }
+        if &header[4..] == box_type {
+            if size > MAX_MOOV_SIZE {
+                return None;
+            }
+            let mut contents = vec![0u8; usize::try_from(size - header_size).ok()?];
+            file.read_exact(&mut contents).ok()?;
+            return Some(contents);
+        }
+        // a malformed size that runs past the end ends the search
+        position = position.checked_add(size)?;
+    }
+    None
+}
+
+fn find_box<'a>(data: &'a [u8], box_type: &[u8; 4]) -> Option<&'a [u8]> {
+    boxes(data)
+        .find(|(t, _)| *t == box_type)
+        .map(|(_, contents)| contents)
+}
+
+// the child boxes in the data, as box type and contents
+fn boxes(data: &[u8]) -> impl Iterator<Item = (&[u8; 4], &[u8])> {
+    let mut offset = 0;
+    std::iter::from_fn(move || {
+        let size = usize::try_from(u32::from_be_bytes(
+            data.get(offset..offset + 4)?.try_into().ok()?,
+        ))
+        .ok()?;
+        let box_type = data.get(offset + 4..offset + 8)?.try_into().ok()?;
+        let contents = data.get(offset + 8..offset + size)?;
+        offset += size.max(8);
+        Some((box_type, contents))
+    })
+}
@@ -0,0 +1,209 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// The image formats that can be reviewed
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum MediaFormat {
+    Jpeg,
+    Png,
+    Gif,
+    Webp,
+    Tiff,
+    Heic,
+    Heif,
+    Avif,
+    Cr2,
+    Cr3,
+    Nef,
+    Arw,
+    Dng,
+    Raf,
+}
+
+impl MediaFormat {
+    pub const ALL: [Self; 14] = [
+        Self::Jpeg,
+        Self::Png,
+        Self::Gif,
+        Self::Webp,
+        Self::Tiff,
+        Self::Heic,
+        Self::Heif,
+        Self::Avif,
+        Self::Cr2,
+        Self::Cr3,
+        Self::Nef,
+        Self::Arw,
+        Self::Dng,
+        Self::Raf,
+    ];
+
+    pub const fn extensions(self) -> &'static [&'static str] {
+        match self {
+            Self::Jpeg => &["jpg", "jpeg"],
+            Self::Png => &["png"],
+            Self::Gif => &["gif"],
+            Self::Webp => &["webp"],
+            Self::Tiff => &["tif", "tiff"],
+            Self::Heic => &["heic"],
+            Self::Heif => &["heif"],
+            Self::Avif => &["avif"],
+            Self::Cr2 => &["cr2"],
+            Self::Cr3 => &["cr3"],
+            Self::Nef => &["nef"],
+            Self::Arw => &["arw"],
+            Self::Dng => &["dng"],
+            Self::Raf => &["raf"],
+        }
+    }
+
+    pub const fn mime_type(self) -> &'static str {
+        match self {
+            Self::Jpeg => "image/jpeg",
+            Self::Png => "image/png",
+            Self::Gif => "image/gif",
+            Self::Webp => "image/webp",
+            Self::Tiff => "image/tiff",
+            Self::Heic => "image/heic",
+            Self::Heif => "image/heif",
+            Self::Avif => "image/avif",
+            Self::Cr2 => "image/x-canon-cr2",
+            Self::Cr3 => "image/x-canon-cr3",
+            Self::Nef => "image/x-nikon-nef",
+            Self::Arw => "image/x-sony-arw",
+            Self::Dng => "image/x-adobe-dng",
+            Self::Raf => "image/x-fuji-raf",
+        }
+    }
+
+    pub const fn is_raw(self) -> bool {
+        matches!(
+            self,
+            Self::Cr2 | Self::Cr3 | Self::Nef | Self::Arw | Self::Dng | Self::Raf
+        )
+    }
+
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|format| {
+            format
+                .extensions()
+                .iter()
+                .any(|e| e.eq_ignore_ascii_case(extension))
+        })
+    }
+
+    /// Detects the format from the magic bytes at the start of the file. The extension is used
+    /// to tell apart formats that share a container, like the TIFF based RAW formats, and as
+    /// fallback when the contents are not recognized.
+    pub fn detect(path: &Path) -> Option<Self> {
+        let from_extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .and_then(Self::from_extension);
+        let mut header = [0u8; 64];
+        let sniffed = File::open(path)
+            .and_then(|mut f| f.read(&mut header))
+            .ok()
+            .and_then(|len| sniff(&header[..len]));
+
+        match (from_extension, sniffed) {
+            (Some(format), Some(sniffed)) if format.is_stored_as(sniffed) => Some(format),
+            (_, Some(sniffed)) => Some(sniffed),
+            (from_extension, None) => from_extension,
+        }
+    }
+
+    /// The format of a photo to review. Only files with the extension of a supported format are
+    /// photos, so that other files are never reviewed whatever their contents, their format is
+    /// then detected like `detect` does.
+    pub fn detect_photo(path: &Path) -> Option<Self> {
+        Self::from_extension(path.extension()?.to_str()?)?;
+        Self::detect(path)
+    }
+
+    // whether files of this format are recognized as `sniffed` by their magic bytes
+    fn is_stored_as(self, sniffed: Self) -> bool {
+        self == sniffed
+            || (sniffed == Self::Tiff && matches!(self, Self::Nef | Self::Arw | Self::Dng))
+            || (sniffed == Self::Heif && matches!(self, Self::Heic | Self::Avif))
+    }
+}
+
+/// Glob pattern that matches all supported formats, to be matched case-insensitively
+pub fn glob_pattern() -> String {
+    let extensions = MediaFormat::ALL
+        .iter()
+        .flat_map(|format| format.extensions())
+        .copied()
+        .collect::<Vec<_>>();
+    format!("**/*.{{{}}}", extensions.join(","))
+}
+
+/// The RAW file that the camera wrote together with the photo, like `DSC_1234.NEF` next to
+/// `DSC_1234.JPG`
+pub fn find_raw_companion(path: &Path) -> Option<PathBuf> {
+    if MediaFormat::detect(path)?.is_raw() {
+        return None;
+    }
+    find_sibling(path, MediaFormat::is_raw)
+}
+
+/// Whether the file is the RAW half of a RAW+JPEG pair, of which the JPEG is shown for review
+pub fn is_raw_companion(path: &Path) -> bool {
+    MediaFormat::detect(path).is_some_and(MediaFormat::is_raw)
+        && find_sibling(path, |format| !format.is_raw()).is_some()
+}
+
+// a file in the same folder with the same file stem and the extension of one of the formats
+fn find_sibling(path: &Path, is_match: impl Fn(MediaFormat) -> bool) -> Option<PathBuf> {
+    MediaFormat::ALL
+        .into_iter()
+        .filter(|format| is_match(*format))
+        .flat_map(MediaFormat::extensions)
+        .flat_map(|e| [(*e).to_string(), e.to_ascii_uppercase()])
+        .map(|e| path.with_extension(e))
+        .find(|sibling| sibling != path && sibling.is_file())
+}
+
+fn sniff(header: &[u8]) -> Option<MediaFormat> {
+    match header {
+        [0xFF, 0xD8, 0xFF, ..] => Some(MediaFormat::Jpeg),
+        [0x89, b'P', b'N', b'G', ..] => Some(MediaFormat::Png),
+        [b'G', b'I', b'F', b'8', ..] => Some(MediaFormat::Gif),
+        _ if header.starts_with(b"RIFF") && header.get(8..12) == Some(b"WEBP") => {
+            Some(MediaFormat::Webp)
+        }
+        [b'I', b'I', 0x2A, 0x00, _, _, _, _, b'C', b'R', 0x02, ..] => Some(MediaFormat::Cr2),
+        [b'I', b'I', 0x2A, 0x00, ..] | [b'M', b'M', 0x00, 0x2A, ..] => Some(MediaFormat::Tiff),
+        _ if header.starts_with(b"FUJIFILMCCD-RAW") => Some(MediaFormat::Raf),
+        [_, _, _, _, b'f', b't', b'y', b'p', ..] => sniff_iso_bmff(header),
+        _ => None,
+    }
+}
+
+// HEIF, AVIF and CR3 files are ISO base media files, told apart by the brands of the ftyp box
+fn sniff_iso_bmff(header: &[u8]) -> Option<MediaFormat> {
+    let box_size = u32::from_be_bytes(header[..4].try_into().ok()?) as usize;
+    let ftyp = header.get(8..box_size.min(header.len()))?;
+    // the major brand, followed by the minor version and the compatible brands
+    let brands = ftyp
+        .chunks_exact(4)
+        .enumerate()
+        .filter(|(i, _)| *i != 1)
+        .map(|(_, brand)| brand)
+        .collect::<Vec<_>>();
+    let has_brand = |names: &[&[u8]]| brands.iter().any(|b| names.contains(b));
+
+    if has_brand(&[b"crx "]) {
+        Some(MediaFormat::Cr3)
+    } else if has_brand(&[b"avif", b"avis"]) {
+        Some(MediaFormat::Avif)
+    } else if has_brand(&[b"heic", b"heix", b"hevc", b"hevx", b"heim", b"heis"]) {
+        Some(MediaFormat::Heic)
+    } else if has_brand(&[b"mif1", b"msf1"]) {
+        Some(MediaFormat::Heif)
+    } else {
+        None
+    }
+}
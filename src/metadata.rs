@@ -0,0 +1,153 @@
+use crate::image::ExifMetadata;
+use chrono::{DateTime, FixedOffset, Local, NaiveDateTime, TimeZone, Utc};
+use exif::{Exif, Field, In, Reader, Tag, Value};
+use std::fs::File;
+use std::io::BufReader;
+
+pub fn read_exif(image_path: &str) -> Option<Exif> {
+    let file = File::open(image_path).ok()?;
+    Reader::new()
+        .read_from_container(&mut BufReader::new(file))
+        .ok()
+}
+
+/// The moment the shutter was pressed, from the EXIF DateTimeOriginal tag in the local time of
+/// the camera and the OffsetTimeOriginal tag. Photos without an offset are taken to be shot in the
+/// time zone of the server, so that they interleave with file modification times.
+pub fn capture_instant(image_path: &str) -> Option<DateTime<Utc>> {
+    let exif = read_exif(image_path)?;
+    let local_time = capture_time_from_exif(&exif)?;
+    let offset = exif
+        .get_field(Tag::OffsetTimeOriginal, In::PRIMARY)
+        .or_else(|| exif.get_field(Tag::OffsetTime, In::PRIMARY))
+        .and_then(ascii)
+        .and_then(|offset| offset.parse::<FixedOffset>().ok());
+    match offset {
+        Some(offset) => offset
+            .from_local_datetime(&local_time)
+            .single()
+            .map(|t| t.with_timezone(&Utc)),
+        None => Local
+            .from_local_datetime(&local_time)
+            .earliest()
+            .map(|t| t.with_timezone(&Utc)),
+    }
+}
+
+pub fn capture_time_from_exif(exif: &Exif) -> Option<NaiveDateTime> {
+    let field = exif
+        .get_field(Tag::DateTimeOriginal, In::PRIMARY)
+        .or_else(|| exif.get_field(Tag::DateTime, In::PRIMARY))?;
+    let Value::Ascii(ref values) = field.value else {
+        return None;
+    };
+    let date_time = exif::DateTime::from_ascii(values.first()?).ok()?;
+    chrono::NaiveDate::from_ymd_opt(
+        date_time.year.into(),
+        date_time.month.into(),
+        date_time.day.into(),
+    )?
+    .and_hms_nano_opt(
+        date_time.hour.into(),
+        date_time.minute.into(),
+        date_time.second.into(),
+        date_time.nanosecond.unwrap_or_default(),
+    )
+}
+
+/// The shooting details of the photo. Pixel dimensions that are missing from the EXIF data are
+/// read from the image header.
+pub fn read_exif_metadata(image_path: &str) -> ExifMetadata {
+    let Some(exif) = read_exif(image_path) else {
+        let (width, height) = image::image_dimensions(image_path).ok().unzip();
+        return ExifMetadata {
+            width,
+            height,
+            ..ExifMetadata::default()
+        };
+    };
+    let field = |tag| exif.get_field(tag, In::PRIMARY);
+    let uint = |tag| field(tag).and_then(|f| f.value.get_uint(0));
+    let (latitude, longitude) = gps_coordinates(&exif).unzip();
+    let (width, height) = uint(Tag::PixelXDimension)
+        .zip(uint(Tag::PixelYDimension))
+        .or_else(|| image::image_dimensions(image_path).ok())
+        .unzip();
+
+    ExifMetadata {
+        capture_time: capture_time_from_exif(&exif),
+        capture_time_offset: field(Tag::OffsetTimeOriginal)
+            .or_else(|| field(Tag::OffsetTime))
+            .and_then(ascii),
+        make: field(Tag::Make).and_then(ascii),
+        model: field(Tag::Model).and_then(ascii),
+        lens: field(Tag::LensModel).and_then(ascii),
+        focal_length: field(Tag::FocalLength).and_then(rational),
+        aperture: field(Tag::FNumber).and_then(rational),
+        exposure_time: field(Tag::ExposureTime).and_then(exposure_time),
+        iso: uint(Tag::PhotographicSensitivity),
+        latitude,
+        longitude,
+        width,
+        height,
+        orientation: uint(Tag::Orientation),
+    }
+}
+
+fn ascii(field: &Field) -> Option<String> {
+    let Value::Ascii(ref values) = field.value else {
+        return None;
+    };
+    let value = String::from_utf8_lossy(values.first()?)
+        .trim_end_matches('\0')
+        .trim()
+        .to_string();
+    (!value.is_empty()).then_some(value)
+}
+
+fn rational(field: &Field) -> Option<f64> {
+    match field.value {
+        Value::Rational(ref values) => values.first().map(exif::Rational::to_f64),
+        Value::SRational(ref values) => values.first().map(exif::SRational::to_f64),
+        _ => None,
+    }
+}
+
+// formatted the way cameras display it, like 1/250 or 2.5 seconds
+fn exposure_time(field: &Field) -> Option<String> {
+    let Value::Rational(ref values) = field.value else {
+        return None;
+    };
+    let exposure_time = values.first()?;
+    if exposure_time.denom == 0 {
+        return None;
+    }
+    if exposure_time.num >= exposure_time.denom {
+        return Some(format!("{}", exposure_time.to_f64()));
+    }
+    Some(format!(
+        "1/{}",
+        (f64::from(exposure_time.denom) / f64::from(exposure_time.num)).round()
+    ))
+}
+
+// latitude and longitude in decimal degrees, negative for south and west
+fn gps_coordinates(exif: &Exif) -> Option<(f64, f64)> {
+    let coordinate = |tag, ref_tag, negative_ref: &str| -> Option<f64> {
+        let Value::Rational(ref dms) = exif.get_field(tag, In::PRIMARY)?.value else {
+            return None;
+        };
+        let degrees = dms.first()?.to_f64()
+            + dms.get(1).map_or(0.0, |m| m.to_f64() / 60.0)
+            + dms.get(2).map_or(0.0, |s| s.to_f64() / 3600.0);
+        let is_negative = exif
+            .get_field(ref_tag, In::PRIMARY)
+            .and_then(ascii)
+            .is_some_and(|r| r.eq_ignore_ascii_case(negative_ref));
+        Some(if is_negative { -degrees } else { degrees })
+    };
+    Some((
+        coordinate(Tag::GPSLatitude, Tag::GPSLatitudeRef, "S")?,
+        coordinate(Tag::GPSLongitude, Tag::GPSLongitudeRef, "W")?,
+    ))
+}
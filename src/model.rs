@@ -1,25 +1,42 @@
-use crate::file_management::FileManager;
-use crate::google_photos_upload::upload_best_photos;
-use crate::image::{PhotoReview, PhotosToReview};
-use crate::reviewscore::ReviewScore;
-use async_graphql::{Context, EmptySubscription, Object, Schema};
+use crate::auth::User;
+use crate::events::{
+    Event, NewPhotosArrived, PhotoReviewed, QueueCountChanged, UploadStatusChanged,
+};
+use crate::file_management::{FileManager, PhotosToReviewRequest, QueueCursor};
+use crate::google_photos_upload::upload_reviewed_photo;
+use crate::image::{
+    DeferredPhotoList, FinishedFolder, FoldersToReview, PhotoOrder, PhotoReview, PhotoReviewInput,
+    PhotosToReview, ReviewResult, ReviewResults,
+};
+use crate::journal::ReviewHistory;
+use crate::reviewscore::{Bucket, ReviewScore, get_buckets};
+use crate::voting::VoteResult;
+use async_graphql::connection::{CursorType, OpaqueCursor, PageInfo};
+use async_graphql::futures_util::{Stream, StreamExt, future};
+use async_graphql::{Context, Object, Schema, Subscription};
 use async_graphql::{OutputType, SimpleObject};
 use std::env;
+use std::path::Path;
+use std::sync::Arc;
 use tracing::error;
 
-pub type ServiceSchema = Schema<QueryRoot, MutationRoot, EmptySubscription>;
+pub type ServiceSchema = Schema<QueryRoot, MutationRoot, SubscriptionRoot>;
 
 #[must_use]
 pub fn new_schema(media_path: Option<&str>) -> ServiceSchema {
     Schema::build(
         QueryRoot::default(),
         MutationRoot::default(),
-        EmptySubscription,
+        SubscriptionRoot::default(),
     )
-    .data(FileManager::new(media_path.map_or_else(
-        || env::var("MEDIA_ROOT").expect("'MEDIA_ROOT' environment variable is required"),
-        std::string::ToString::to_string,
-    )))
+    // the server watches MEDIA_ROOT for new photos, schemas over a given media path walk it
+    .data(match media_path {
+        Some(media_path) => FileManager::new(media_path.into()),
+        None => FileManager::new(
+            env::var("MEDIA_ROOT").expect("'MEDIA_ROOT' environment variable is required"),
+        )
+        .watching(),
+    })
     .finish()
 }
 
@@ -28,13 +45,21 @@ pub struct QueryRoot {}
 
 #[Object]
 impl QueryRoot {
+    /// Pass the `path` of one of the foldersToReview as `folder` to review that folder instead of
+    /// the first folder with images to review.
+    /// The photos are ordered by file name, unless `orderBy` is passed.
     ///{
-    ///  photosToReview{
+    ///  photosToReview(first: 20, orderBy: {field: CAPTURE_TIME, direction: ASC}){
     ///     output {
     ///        baseUrl
     ///        photos{
     ///          album
     ///          url
+    ///          cursor
+    ///        }
+    ///        pageInfo {
+    ///          hasNextPage
+    ///          endCursor
     ///        }
     ///        folderName
     ///        folderImageCount
@@ -42,8 +67,32 @@ impl QueryRoot {
     ///  }
     ///}
     #[graphql(name = "photosToReview")]
-    async fn photos_to_review(&self, ctx: &Context<'_>) -> Response<PhotosToReview> {
-        match ctx.data::<FileManager>().unwrap().get_photos_to_review() {
+    async fn photos_to_review(
+        &self,
+        ctx: &Context<'_>,
+        folder: Option<String>,
+        #[graphql(default)] order_by: PhotoOrder,
+        #[graphql(default = 20, validator(minimum = 1, maximum = 200))] first: usize,
+        after: Option<String>,
+    ) -> Response<PhotosToReview> {
+        match after
+            .map(|cursor| {
+                OpaqueCursor::<QueueCursor>::decode_cursor(&cursor)
+                    .map(|c| c.0)
+                    .map_err(|e| anyhow::anyhow!("invalid cursor '{cursor}': {e}"))
+            })
+            .transpose()
+            .and_then(|after| {
+                ctx.data::<FileManager>()
+                    .unwrap()
+                    .get_photos_to_review(&PhotosToReviewRequest {
+                        folder,
+                        order: order_by,
+                        first,
+                        after,
+                        user: current_user(ctx),
+                    })
+            }) {
             Ok(paths) => Response::succeeded(paths),
             Err(err) => {
                 error!("Failed to retrieve photos_to_review: {:#}", err);
@@ -52,6 +101,13 @@ impl QueryRoot {
                     output: PhotosToReview {
                         base_url: String::new(),
                         photos: vec![],
+                        groups: vec![],
+                        page_info: PageInfo {
+                            has_previous_page: false,
+                            has_next_page: false,
+                            start_cursor: None,
+                            end_cursor: None,
+                        },
                         folder_name: String::new(),
                         folder_image_count: 0,
                     },
@@ -59,6 +115,119 @@ impl QueryRoot {
             }
         }
     }
+
+    /// All folders with images to review
+    ///{
+    ///  foldersToReview {
+    ///    output {
+    ///      folders {
+    ///        path
+    ///        name
+    ///        imageCount
+    ///        oldest
+    ///        newest
+    ///      }
+    ///    }
+    ///  }
+    ///}
+    #[graphql(name = "foldersToReview")]
+    async fn folders_to_review(&self, ctx: &Context<'_>) -> Response<FoldersToReview> {
+        match ctx
+            .data::<FileManager>()
+            .unwrap()
+            .get_folders_to_review(&current_user(ctx))
+        {
+            Ok(folders) => Response::succeeded(folders),
+            Err(err) => {
+                error!("Failed to retrieve folders to review: {:#}", err);
+                Response {
+                    success: false,
+                    output: FoldersToReview { folders: vec![] },
+                }
+            }
+        }
+    }
+
+    /// The photos that were deferred with deferPhoto, optionally only those in `folder`
+    ///{
+    ///  deferredPhotos(folder: "albumX") {
+    ///    output {
+    ///      photos {
+    ///        url
+    ///        album
+    ///        deferredAt
+    ///      }
+    ///    }
+    ///  }
+    ///}
+    #[graphql(name = "deferredPhotos")]
+    async fn deferred_photos(
+        &self,
+        ctx: &Context<'_>,
+        folder: Option<String>,
+    ) -> Response<DeferredPhotoList> {
+        match ctx
+            .data::<FileManager>()
+            .unwrap()
+            .get_deferred_photos(folder.as_deref(), &current_user(ctx))
+        {
+            Ok(photos) => Response::succeeded(photos),
+            Err(err) => {
+                error!("Failed to retrieve deferred photos: {:#}", err);
+                Response {
+                    success: false,
+                    output: DeferredPhotoList { photos: vec![] },
+                }
+            }
+        }
+    }
+
+    /// The buckets that photos can be reviewed into, ordered by their display order
+    #[graphql(name = "reviewBuckets")]
+    async fn review_buckets(&self) -> Vec<Bucket> {
+        get_buckets().to_vec()
+    }
+
+    ///{
+    ///  reviewHistory(limit: 50) {
+    ///    output {
+    ///      entries {
+    ///        id
+    ///        action
+    ///        source
+    ///        destination
+    ///        score
+    ///        timestamp
+    ///      }
+    ///      nextCursor
+    ///    }
+    ///  }
+    ///}
+    #[graphql(name = "reviewHistory")]
+    async fn review_history(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default = 50, validator(minimum = 1, maximum = 200))] limit: usize,
+        cursor: Option<u64>,
+    ) -> Response<ReviewHistory> {
+        match ctx.data::<FileManager>().unwrap().get_review_history(
+            limit,
+            cursor,
+            &current_user(ctx),
+        ) {
+            Ok(history) => Response::succeeded(history),
+            Err(err) => {
+                error!("Failed to retrieve review history: {:#}", err);
+                Response {
+                    success: false,
+                    output: ReviewHistory {
+                        entries: vec![],
+                        next_cursor: None,
+                    },
+                }
+            }
+        }
+    }
 }
 
 #[derive(Default)]
@@ -79,15 +248,341 @@ impl MutationRoot {
         path: String,
         score: ReviewScore,
     ) -> Response<String> {
+        let file_manager = ctx.data::<FileManager>().unwrap();
+        match file_manager.new_image(&path).and_then(|image| {
+            file_manager.review_photo(&PhotoReview { image, score }, &current_user(ctx))
+        }) {
+            Ok(reviewed) => {
+                upload(reviewed, file_manager);
+                Response::succeeded(String::new())
+            }
+            Err(err) => {
+                error!("Failed to review photo '{}': {:#}", path, err);
+                Response {
+                    success: false,
+                    output: err.to_string(),
+                }
+            }
+        }
+    }
+
+    /// Casts a vote on the score of a photo when photos are reviewed by vote, see VOTING_CONFIG.
+    /// The photo is reviewed once the votes resolve according to the voting rule, until then it
+    /// stays in the review queue.
+    ///     mutation {
+    ///       votePhoto(path:"/albumx/testphoto.jpg", score: BEST) {
+    ///          success
+    ///          output {
+    ///            votes { voter score votedAt }
+    ///            resolvedScore
+    ///            destination
+    ///          }
+    ///       }
+    ///     }
+    #[graphql(name = "votePhoto")]
+    async fn vote_photo(
+        &self,
+        ctx: &Context<'_>,
+        path: String,
+        score: ReviewScore,
+    ) -> Response<VoteResult> {
         let file_manager = ctx.data::<FileManager>().unwrap();
         match file_manager
             .new_image(&path)
-            .and_then(|image| file_manager.review_photo(&PhotoReview { image, score }))
-            .and_then(upload_best_photos)
+            .and_then(|image| file_manager.vote_photo(&image, score, &current_user(ctx)))
         {
-            Ok(()) => Response::succeeded(String::new()),
+            Ok((result, reviewed)) => {
+                if let Some(reviewed) = reviewed {
+                    upload(reviewed, file_manager);
+                }
+                Response::succeeded(result)
+            }
             Err(err) => {
-                error!("Failed to review photo '{}': {:#}", path, err);
+                error!("Failed to vote on photo '{}': {:#}", path, err);
+                Response {
+                    success: false,
+                    output: VoteResult {
+                        votes: vec![],
+                        resolved_score: None,
+                        destination: None,
+                        message: Some(err.to_string()),
+                    },
+                }
+            }
+        }
+    }
+
+    /// Reviews all photos or none of them: when one of the photos cannot be reviewed, the photos
+    /// that were already moved are moved back. The output has the result of every photo.
+    ///     mutation {
+    ///       reviewPhotos(items: [{path: "/media/albumx/1.jpg", score: BEST}, {path: "/media/albumx/2.jpg", score: WORST}]) {
+    ///          success
+    ///          output {
+    ///            items { path success output }
+    ///          }
+    ///       }
+    ///     }
+    #[graphql(name = "reviewPhotos")]
+    async fn review_photos(
+        &self,
+        ctx: &Context<'_>,
+        items: Vec<PhotoReviewInput>,
+    ) -> Response<ReviewResults> {
+        let file_manager = ctx.data::<FileManager>().unwrap();
+        let reviews = items
+            .iter()
+            .map(|item| {
+                file_manager.new_image(&item.path).map(|image| PhotoReview {
+                    image,
+                    score: item.score,
+                })
+            })
+            .collect::<Vec<_>>();
+        let results = if reviews.iter().all(Result::is_ok) {
+            file_manager.review_photos(
+                &reviews.into_iter().flatten().collect::<Vec<_>>(),
+                &current_user(ctx),
+            )
+        } else {
+            reviews
+                .into_iter()
+                .map(|review| {
+                    review.and_then(|_| {
+                        anyhow::bail!("Not reviewed because another photo of the batch is invalid")
+                    })
+                })
+                .collect()
+        };
+
+        let success = results.iter().all(Result::is_ok);
+        let items = items
+            .into_iter()
+            .zip(results)
+            .map(|(item, result)| match result {
+                Ok(reviewed) => {
+                    let url = reviewed.image.url();
+                    upload(reviewed, file_manager);
+                    ReviewResult {
+                        path: item.path,
+                        success: true,
+                        output: url,
+                    }
+                }
+                Err(err) => {
+                    error!("Failed to review photo '{}': {:#}", item.path, err);
+                    ReviewResult {
+                        path: item.path,
+                        success: false,
+                        output: err.to_string(),
+                    }
+                }
+            })
+            .collect();
+        Response {
+            success,
+            output: ReviewResults { items },
+        }
+    }
+
+    /// Reviews all photos that are left in the folder with the same score, typically after the
+    /// highlights have been picked. Pass the `path` of one of the foldersToReview as `folder`.
+    ///     mutation {
+    ///       finishFolder(folder: "trips/rome", score: GOOD) {
+    ///          success
+    ///          output {
+    ///            reviewedCount
+    ///            failedCount
+    ///            items { path success output }
+    ///          }
+    ///       }
+    ///     }
+    #[graphql(name = "finishFolder")]
+    async fn finish_folder(
+        &self,
+        ctx: &Context<'_>,
+        folder: String,
+        score: ReviewScore,
+    ) -> Response<FinishedFolder> {
+        let file_manager = ctx.data::<FileManager>().unwrap();
+        let results = match file_manager.finish_folder(&folder, score, &current_user(ctx)) {
+            Ok(results) => results,
+            Err(err) => {
+                error!("Failed to finish folder '{}': {:#}", folder, err);
+                return Response {
+                    success: false,
+                    output: FinishedFolder {
+                        reviewed_count: 0,
+                        failed_count: 0,
+                        items: vec![],
+                    },
+                };
+            }
+        };
+        let items = results
+            .into_iter()
+            .map(|(image, result)| match result {
+                Ok(reviewed) => {
+                    let url = reviewed.image.url();
+                    upload(reviewed, file_manager);
+                    ReviewResult {
+                        path: image.url(),
+                        success: true,
+                        output: url,
+                    }
+                }
+                Err(err) => {
+                    error!("Failed to review photo '{}': {:#}", image.full_path, err);
+                    ReviewResult {
+                        path: image.url(),
+                        success: false,
+                        output: err.to_string(),
+                    }
+                }
+            })
+            .collect::<Vec<_>>();
+        let reviewed_count = items.iter().filter(|item| item.success).count();
+        let failed_count = items.len() - reviewed_count;
+        Response {
+            success: failed_count == 0,
+            output: FinishedFolder {
+                reviewed_count,
+                failed_count,
+                items,
+            },
+        }
+    }
+
+    /// Writes a star rating (0-5), color label and tags to the XMP sidecar of the photo. Fields
+    /// that are omitted keep their current value. When a score is passed, the photo is moved to
+    /// that bucket together with its sidecar.
+    ///     mutation {
+    ///       ratePhoto(path:"/media/albumx/testphoto.jpg", stars: 4, label: "Green", tags: ["family"]) {
+    ///          success
+    ///          output
+    ///       }
+    ///     }
+    #[graphql(name = "ratePhoto")]
+    async fn rate_photo(
+        &self,
+        ctx: &Context<'_>,
+        path: String,
+        stars: Option<i32>,
+        label: Option<String>,
+        tags: Option<Vec<String>>,
+        score: Option<ReviewScore>,
+    ) -> Response<String> {
+        let file_manager = ctx.data::<FileManager>().unwrap();
+        match file_manager.new_image(&path).and_then(|image| {
+            if stars.is_some_and(|s| !(0..=5).contains(&s)) {
+                anyhow::bail!("stars must be between 0 and 5");
+            }
+            file_manager.rate_photo(&image, stars, label, tags, score, &current_user(ctx))
+        }) {
+            Ok(reviewed) => {
+                if let Some(reviewed) = reviewed {
+                    upload(reviewed, file_manager);
+                }
+                Response::succeeded(String::new())
+            }
+            Err(err) => {
+                error!("Failed to rate photo '{}': {:#}", path, err);
+                Response {
+                    success: false,
+                    output: err.to_string(),
+                }
+            }
+        }
+    }
+
+    /// Reviews a group of similar photos at once, typically a burst: the photo to keep gets
+    /// `keepScore` and all other photos of the group get `othersScore`. These default to the
+    /// first and the last review bucket.
+    ///     mutation {
+    ///       reviewGroup(keep:"/media/albumx/burst-2.jpg", others: ["/media/albumx/burst-1.jpg"]) {
+    ///          success
+    ///          output
+    ///       }
+    ///     }
+    #[graphql(name = "reviewGroup")]
+    async fn review_group(
+        &self,
+        ctx: &Context<'_>,
+        keep: String,
+        others: Vec<String>,
+        #[graphql(default_with = "ReviewScore::first()")] keep_score: ReviewScore,
+        #[graphql(default_with = "ReviewScore::last()")] others_score: ReviewScore,
+    ) -> Response<String> {
+        let file_manager = ctx.data::<FileManager>().unwrap();
+        let user = current_user(ctx);
+        let reviews = std::iter::once((keep.as_str(), keep_score))
+            .chain(others.iter().map(|path| (path.as_str(), others_score)))
+            .map(|(path, score)| {
+                file_manager
+                    .new_image(path)
+                    .map(|image| PhotoReview { image, score })
+            })
+            .collect::<anyhow::Result<Vec<PhotoReview>>>();
+        // the group is reviewed all or nothing, like reviewPhotos
+        let results = match reviews {
+            Ok(reviews) => file_manager.review_photos(&reviews, &user),
+            Err(err) => vec![Err(err)],
+        };
+        if results.iter().any(Result::is_err) {
+            let output = std::iter::once(keep.as_str())
+                .chain(others.iter().map(String::as_str))
+                .zip(&results)
+                .filter_map(|(path, result)| Some(format!("{path}: {}", result.as_ref().err()?)))
+                .collect::<Vec<_>>()
+                .join("; ");
+            error!("Failed to review photo group of '{}': {}", keep, output);
+            return Response {
+                success: false,
+                output,
+            };
+        }
+        for reviewed in results.into_iter().flatten() {
+            upload(reviewed, file_manager);
+        }
+        Response::succeeded(String::new())
+    }
+
+    /// Postpones the review of a photo without moving it: photosToReview skips it until it is
+    /// reviewed or undeferred, and deferredPhotos lists it.
+    ///     mutation {
+    ///       deferPhoto(path:"/albumx/testphoto.jpg") {
+    ///          success
+    ///          output
+    ///       }
+    ///     }
+    #[graphql(name = "deferPhoto")]
+    async fn defer_photo(&self, ctx: &Context<'_>, path: String) -> Response<String> {
+        let file_manager = ctx.data::<FileManager>().unwrap();
+        match file_manager
+            .new_image(&path)
+            .and_then(|image| file_manager.defer_photo(&image, &current_user(ctx)))
+        {
+            Ok(()) => Response::succeeded(path),
+            Err(err) => {
+                error!("Failed to defer photo '{}': {:#}", path, err);
+                Response {
+                    success: false,
+                    output: err.to_string(),
+                }
+            }
+        }
+    }
+
+    /// Returns a deferred photo to the review queue
+    #[graphql(name = "undeferPhoto")]
+    async fn undefer_photo(&self, ctx: &Context<'_>, path: String) -> Response<String> {
+        let file_manager = ctx.data::<FileManager>().unwrap();
+        match file_manager
+            .new_image(&path)
+            .and_then(|image| file_manager.undefer_photo(&image, &current_user(ctx)))
+        {
+            Ok(()) => Response::succeeded(path),
+            Err(err) => {
+                error!("Failed to undefer photo '{}': {:#}", path, err);
                 Response {
                     success: false,
                     output: err.to_string(),
@@ -101,7 +596,7 @@ impl MutationRoot {
         let file_manager = ctx.data::<FileManager>().unwrap();
         match file_manager
             .new_image(&path)
-            .and_then(|image| FileManager::undo(&PhotoReview { image, score }))
+            .and_then(|image| file_manager.undo(&PhotoReview { image, score }, &current_user(ctx)))
         {
             Ok(()) => Response::succeeded(String::new()),
             Err(err) => {
@@ -113,11 +608,176 @@ impl MutationRoot {
             }
         }
     }
+
+    /// Undoes the most recent review. The output is the url of the restored photo.
+    ///     mutation {
+    ///       undoLast {
+    ///          success
+    ///          output
+    ///       }
+    ///     }
+    #[graphql(name = "undoLast")]
+    async fn undo_last(&self, ctx: &Context<'_>) -> Response<String> {
+        match ctx
+            .data::<FileManager>()
+            .unwrap()
+            .undo_last(&current_user(ctx))
+        {
+            Ok(image) => Response::succeeded(image.url()),
+            Err(err) => {
+                error!("Failed to undo last review: {:#}", err);
+                Response {
+                    success: false,
+                    output: err.to_string(),
+                }
+            }
+        }
+    }
+
+    /// Reapplies the most recently undone review. The output is the url of the reviewed photo
+    /// before it was moved.
+    #[graphql(name = "redo")]
+    async fn redo(&self, ctx: &Context<'_>) -> Response<String> {
+        let file_manager = ctx.data::<FileManager>().unwrap();
+        match file_manager.redo(&current_user(ctx)) {
+            Ok((image, reviewed)) => {
+                upload(reviewed, file_manager);
+                Response::succeeded(image.url())
+            }
+            Err(err) => {
+                error!("Failed to redo review: {:#}", err);
+                Response {
+                    success: false,
+                    output: err.to_string(),
+                }
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct SubscriptionRoot {}
+
+/// Subscriptions are served over the websocket at `/ws`
+///     subscription {
+///       photoReviewed {
+///         path
+///         destination
+///         score
+///       }
+///     }
+#[Subscription]
+impl SubscriptionRoot {
+    /// Photos that are reviewed by any client, including redone reviews
+    #[graphql(name = "photoReviewed")]
+    async fn photo_reviewed(&self, ctx: &Context<'_>) -> impl Stream<Item = PhotoReviewed> {
+        events(ctx, |event| match event {
+            Event::PhotoReviewed(reviewed) => Some(reviewed),
+            _ => None,
+        })
+    }
+
+    /// Photos that appear in the folders to review, optionally only in `folder`. Only available
+    /// when the server watches the media root.
+    #[graphql(name = "newPhotosArrived")]
+    async fn new_photos_arrived(
+        &self,
+        ctx: &Context<'_>,
+        folder: Option<String>,
+    ) -> impl Stream<Item = NewPhotosArrived> {
+        events(ctx, move |event| match event {
+            Event::NewPhotosArrived(arrived)
+                if is_in_folder(&arrived.folder, folder.as_deref()) =>
+            {
+                Some(arrived)
+            }
+            _ => None,
+        })
+    }
+
+    /// Progress of the uploads of reviewed photos to Google Photos
+    #[graphql(name = "uploadStatusChanged")]
+    async fn upload_status_changed(
+        &self,
+        ctx: &Context<'_>,
+    ) -> impl Stream<Item = UploadStatusChanged> {
+        events(ctx, |event| match event {
+            Event::UploadStatusChanged(changed) => Some(changed),
+            _ => None,
+        })
+    }
+
+    /// The number of photos left to review in a folder after a review, undo, (un)deferral or the
+    /// arrival of new photos, optionally only for `folder`
+    #[graphql(name = "queueCountChanged")]
+    async fn queue_count_changed(
+        &self,
+        ctx: &Context<'_>,
+        folder: Option<String>,
+    ) -> impl Stream<Item = QueueCountChanged> {
+        events(ctx, move |event| match event {
+            Event::QueueCountChanged(changed)
+                if is_in_folder(&changed.folder, folder.as_deref()) =>
+            {
+                Some(changed)
+            }
+            _ => None,
+        })
+    }
+}
+
+// the user is added to the request by the auth middleware, or to the websocket connection on
+// connection_init. Schemas that are used without the server, like in tests, act as the anonymous
+// user of a server without authentication.
+fn current_user(ctx: &Context<'_>) -> User {
+    ctx.data_opt::<User>()
+        .cloned()
+        .unwrap_or_else(User::anonymous)
+}
+
+// the photo is reviewed once it has been moved to its bucket, so a failed upload is logged
+// rather than reported as a failed review
+fn upload(reviewed: PhotoReview, file_manager: &FileManager) {
+    let url = reviewed.image.url();
+    if let Err(e) = upload_reviewed_photo(reviewed, file_manager.events()) {
+        error!("Failed to upload photo '{}': {:#}", url, e);
+    }
+}
+
+// the events of the media root that `select` picks, from now on, of the photos that the user
+// has access to
+fn events<T, F>(ctx: &Context<'_>, select: F) -> impl Stream<Item = T> + use<T, F>
+where
+    F: Fn(Event) -> Option<T> + Send + 'static,
+{
+    let user = current_user(ctx);
+    let file_manager = ctx.data::<FileManager>().unwrap();
+    let origins = Arc::clone(file_manager.origins());
+    file_manager.events().subscribe().filter_map(move |event| {
+        let relative_path = match &event {
+            Event::PhotoReviewed(reviewed) => reviewed.path.trim_start_matches("/media/"),
+            Event::UploadStatusChanged(changed) => changed.path.trim_start_matches("/media/"),
+            Event::NewPhotosArrived(arrived) => &arrived.folder,
+            Event::QueueCountChanged(changed) => &changed.folder,
+        };
+        let is_visible = user.can_access(Path::new(relative_path), &origins);
+        future::ready(if is_visible { select(event) } else { None })
+    })
+}
+
+fn is_in_folder(event_folder: &str, folder: Option<&str>) -> bool {
+    folder.is_none_or(|folder| folder.trim_matches('/') == event_folder)
 }
 
 #[derive(SimpleObject)]
 #[graphql(concrete(name = "MutationReponseString", params(String)))]
 #[graphql(concrete(name = "MutationResponsePhotosToReview", params(PhotosToReview)))]
+#[graphql(concrete(name = "QueryResponseReviewHistory", params(ReviewHistory)))]
+#[graphql(concrete(name = "QueryResponseFoldersToReview", params(FoldersToReview)))]
+#[graphql(concrete(name = "MutationResponseReviewResults", params(ReviewResults)))]
+#[graphql(concrete(name = "MutationResponseFinishedFolder", params(FinishedFolder)))]
+#[graphql(concrete(name = "QueryResponseDeferredPhotos", params(DeferredPhotoList)))]
+#[graphql(concrete(name = "MutationResponseVoteResult", params(VoteResult)))]
 pub struct Response<T: OutputType> {
     success: bool,
     output: T,
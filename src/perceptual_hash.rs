@@ -0,0 +1,52 @@
+use anyhow::{Context, Result};
+use image::ImageReader;
+
+// maximum number of differing bits of the 64 bit dHash for two photos to be considered similar
+pub const SIMILARITY_THRESHOLD: u32 = 10;
+
+/// Computes the 64 bit difference hash (dHash) of the image: the image is scaled down to 9x8
+/// grayscale pixels and every bit records whether brightness increases between neighbouring
+/// pixels. Near-identical photos, like the shots of a burst, have hashes that differ in a few
+/// bits only.
+pub fn dhash(image_path: &str) -> Result<u64> {
+    let small = ImageReader::open(image_path)?
+        .with_guessed_format()?
+        .decode()
+        .with_context(|| format!("Failed to decode {image_path}"))?
+        .thumbnail_exact(9, 8)
+        .to_luma8();
+
+    let mut hash = 0u64;
+    for y in 0..8 {
+        for x in 0..8 {
+            hash <<= 1;
+            if small.get_pixel(x, y)[0] < small.get_pixel(x + 1, y)[0] {
+                hash |= 1;
+            }
+        }
+    }
+    Ok(hash)
+}
+
+pub const fn distance(a: u64, b: u64) -> u32 {
+    (a ^ b).count_ones()
+}
+
+/// Groups consecutive items with similar hashes. Items without a hash end up in a group of
+/// their own.
+pub fn group_similar<T>(items: Vec<T>, hash: impl Fn(&T) -> Option<u64>) -> Vec<Vec<T>> {
+    let mut groups: Vec<Vec<T>> = vec![];
+    for item in items {
+        let is_similar_to_previous = groups
+            .last()
+            .and_then(|group| group.last())
+            .and_then(&hash)
+            .zip(hash(&item))
+            .is_some_and(|(previous, current)| distance(previous, current) <= SIMILARITY_THRESHOLD);
+        match groups.last_mut() {
+            Some(group) if is_similar_to_previous => group.push(item),
+            _ => groups.push(vec![item]),
+        }
+    }
+    groups
+}
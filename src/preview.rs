@@ -0,0 +1,224 @@
+use crate::file_management::STATE_DIR_NAME;
+use crate::media_format::MediaFormat;
+use crate::rendition;
+use anyhow::{Context, Result, bail};
+use image::codecs::jpeg::JpegEncoder;
+use image::imageops::FilterType;
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::SystemTime;
+use tracing::{error, info};
+
+const PREVIEW_DIR_NAME: &str = "previews";
+const DEFAULT_MAX_CACHE_MB: u64 = 1024;
+const JPEG_QUALITY: u8 = 80;
+// part of the cache key, to be increased whenever previews are rendered differently, like after
+// a change of the rotation or color conversion, so that outdated previews are not served
+const RENDER_VERSION: u32 = 1;
+
+static NEXT_TEMPORARY_ID: AtomicU64 = AtomicU64::new(0);
+
+/// The sizes that previews are rendered in. Only a few sizes are offered so that the cache is
+/// shared between clients.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum PreviewSize {
+    /// For grids and filmstrips
+    Thumbnail,
+    /// For viewing a photo full screen
+    Large,
+    /// Full resolution, for photos that browsers cannot display
+    Full,
+}
+
+impl PreviewSize {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "thumbnail" => Some(Self::Thumbnail),
+            "large" => Some(Self::Large),
+            "full" => Some(Self::Full),
+            _ => None,
+        }
+    }
+
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Thumbnail => "thumbnail",
+            Self::Large => "large",
+            Self::Full => "full",
+        }
+    }
+
+    /// Length of the longest side in pixels
+    pub const fn max_dimension(self) -> Option<u32> {
+        match self {
+            Self::Thumbnail => Some(320),
+            Self::Large => Some(1920),
+            Self::Full => None,
+        }
+    }
+
+    /// Url of the preview of the photo at the path relative to the media root
+    pub fn url(self, relative_path: &str) -> String {
+        format!("/preview/{}/{relative_path}", self.as_str())
+    }
+}
+
+/// JPEG renditions of the photos, downscaled except for the full size, cached on disk under the
+/// state folder. Cached previews are keyed on the size and modification time of the photo and on
+/// the version of the rendering, so a changed photo or renderer gets a new preview, and the least
+/// recently used previews are removed when the cache outgrows its limit.
+pub struct PreviewCache {
+    root_dir: PathBuf,
+    cache_dir: PathBuf,
+    max_bytes: u64,
+}
+
+impl PreviewCache {
+    pub fn new(media_path: &str, max_bytes: u64) -> Self {
+        Self {
+            root_dir: media_path.into(),
+            cache_dir: PathBuf::from(media_path)
+                .join(STATE_DIR_NAME)
+                .join(PREVIEW_DIR_NAME),
+            max_bytes,
+        }
+    }
+
+    /// The cache limit is read from `PREVIEW_CACHE_MAX_MB`
+    pub fn from_env(media_path: &str) -> Self {
+        let max_mb = std::env::var("PREVIEW_CACHE_MAX_MB")
+            .ok()
+            .and_then(|mb| mb.parse().ok())
+            .unwrap_or(DEFAULT_MAX_CACHE_MB);
+        Self::new(media_path, max_mb * 1024 * 1024)
+    }
+
+    /// Returns the path of the cached preview of the photo at the path relative to the media
+    /// root, rendering it first when there is no up to date preview
+    pub fn get(&self, relative_path: &str, size: PreviewSize) -> Result<PathBuf> {
+        let source = self.resolve(relative_path)?;
+        let metadata =
+            fs::metadata(&source).with_context(|| format!("Photo not found: {relative_path}"))?;
+        let modified = metadata
+            .modified()?
+            .duration_since(SystemTime::UNIX_EPOCH)?
+            .as_nanos();
+        let key = blake3::hash(
+            format!(
+                "{RENDER_VERSION}\n{relative_path}\n{}\n{modified}\n{}",
+                size.as_str(),
+                metadata.len()
+            )
+            .as_bytes(),
+        );
+        let cached = self.cache_dir.join(format!("{}.jpg", key.to_hex()));
+
+        if cached.exists() {
+            // the modification time of a preview is the last time it was used
+            if let Err(e) = File::options()
+                .append(true)
+                .open(&cached)
+                .and_then(|f| f.set_modified(SystemTime::now()))
+            {
+                error!("Failed to touch preview {}: {}", cached.display(), e);
+            }
+            return Ok(cached);
+        }
+
+        fs::create_dir_all(&self.cache_dir)?;
+        render(&source, &cached, size)?;
+        self.evict(&cached);
+        Ok(cached)
+    }
+
+    /// The path and mime type of the photo itself, for photos that browsers can display when
+    /// their preview cannot be rendered, like AVIF photos that are not decoded
+    pub fn original(&self, relative_path: &str) -> Option<(PathBuf, &'static str)> {
+        let source = self.resolve(relative_path).ok()?;
+        let format = MediaFormat::detect_photo(&source)?;
+        (!rendition::needs_rendition(format)).then(|| (source, format.mime_type()))
+    }
+
+    // only paths inside the media root can be previewed
+    fn resolve(&self, relative_path: &str) -> Result<PathBuf> {
+        let path = Path::new(relative_path);
+        if path
+            .components()
+            .any(|c| !matches!(c, Component::Normal(_)))
+        {
+            bail!("Invalid path '{relative_path}'");
+        }
+        Ok(self.root_dir.join(path))
+    }
+
+    // removes the least recently used previews until the cache fits within its limit, the
+    // preview that was just rendered is kept even when it exceeds the limit by itself
+    fn evict(&self, keep: &Path) {
+        let Ok(entries) = fs::read_dir(&self.cache_dir) else {
+            return;
+        };
+        let mut previews = entries
+            .filter_map(Result::ok)
+            // previews that are being rendered are left alone
+            .filter(|entry| entry.path().extension().is_some_and(|e| e == "jpg"))
+            .filter_map(|entry| {
+                let metadata = entry.metadata().ok()?;
+                Some((metadata.modified().ok()?, metadata.len(), entry.path()))
+            })
+            .collect::<Vec<_>>();
+        let mut total: u64 = previews.iter().map(|(_, len, _)| len).sum();
+        if total <= self.max_bytes {
+            return;
+        }
+        previews.sort();
+        for (_, len, path) in previews.into_iter().filter(|(_, _, path)| path != keep) {
+            if total <= self.max_bytes {
+                break;
+            }
+            match fs::remove_file(&path) {
+                Ok(()) => total -= len,
+                Err(e) => error!("Failed to remove preview {}: {}", path.display(), e),
+            }
+        }
+    }
+}
+
+// decodes, downscales and encodes the photo as JPEG. The preview is written to a temporary file
+// first so that concurrent requests never see a partial preview.
+fn render(source: &Path, destination: &Path, size: PreviewSize) -> Result<()> {
+    info!(
+        "Rendering {} preview of {}",
+        size.as_str(),
+        source.display()
+    );
+    let image = rendition::decode(source)
+        .with_context(|| format!("Failed to decode {}", source.display()))?;
+    let image = match size.max_dimension() {
+        Some(max) if image.width() > max || image.height() > max => {
+            image.resize(max, max, FilterType::Lanczos3)
+        }
+        _ => image,
+    };
+
+    let temporary = destination.with_extension(format!(
+        "{}.tmp",
+        NEXT_TEMPORARY_ID.fetch_add(1, Ordering::Relaxed)
+    ));
+    let mut writer = BufWriter::new(File::create(&temporary)?);
+    let result = JpegEncoder::new_with_quality(&mut writer, JPEG_QUALITY)
+        .encode_image(&image.to_rgb8())
+        .map_err(anyhow::Error::from)
+        .and_then(|()| {
+            Ok(writer
+                .into_inner()
+                .map_err(|e| e.into_error())?
+                .sync_all()?)
+        })
+        .and_then(|()| Ok(fs::rename(&temporary, destination)?));
+    if result.is_err() {
+        let _ = fs::remove_file(&temporary);
+    }
+    result
+}
@@ -0,0 +1,226 @@
+use crate::media_format::MediaFormat;
+use crate::metadata::read_exif;
+use anyhow::{Context, Result, bail};
+use exif::{In, Tag};
+use image::metadata::Orientation;
+use image::{DynamicImage, ImageDecoder, ImageFormat, ImageReader};
+use qcms::{DataType, Intent, Profile, Transform};
+use std::io::Cursor;
+use std::path::Path;
+use std::process::Command;
+use std::sync::LazyLock;
+use std::{env, fs};
+use tracing::{error, info, warn};
+
+// command that converts a HEIF file to JPEG, invoked as `<command> <input> <output.jpg>`
+static HEIF_CONVERTER: LazyLock<Option<String>> = LazyLock::new(|| {
+    env::var("HEIF_CONVERTER")
+        .ok()
+        .filter(|c| !c.is_empty())
+        .or_else(|| is_on_path("heif-convert").then(|| "heif-convert".into()))
+});
+
+/// Logs a warning when no HEIF converter is available, HEIC photos are then served as they are
+pub fn warn_without_heif_converter() {
+    match HEIF_CONVERTER.as_deref() {
+        Some(converter) if is_on_path(converter) || Path::new(converter).is_file() => {}
+        Some(converter) => {
+            warn!("HEIF converter '{converter}' not found, HEIC photos are served as they are")
+        }
+        None => warn!(
+            "No HEIF converter found, HEIC photos are served as they are. Set HEIF_CONVERTER or install heif-convert to preview them."
+        ),
+    }
+}
+
+/// Whether browsers can display the original, otherwise photos are shown from a JPEG rendition
+pub fn needs_rendition(format: MediaFormat) -> bool {
+    match format {
+        MediaFormat::Jpeg
+        | MediaFormat::Png
+        | MediaFormat::Gif
+        | MediaFormat::Webp
+        | MediaFormat::Avif => false,
+        // without a converter the original is served, which Safari can display
+        MediaFormat::Heic | MediaFormat::Heif => HEIF_CONVERTER.is_some(),
+        MediaFormat::Tiff
+        | MediaFormat::Cr2
+        | MediaFormat::Cr3
+        | MediaFormat::Nef
+        | MediaFormat::Arw
+        | MediaFormat::Dng
+        | MediaFormat::Raf => true,
+    }
+}
+
+/// Decodes the photo upright and in sRGB, the way it is meant to be seen. RAW files are not
+/// developed, the JPEG preview that the camera embedded is used instead. HEIF files are decoded
+/// by the external `HEIF_CONVERTER` command, which defaults to `heif-convert` of libheif when it
+/// is installed.
+pub fn decode(path: &Path) -> Result<DynamicImage> {
+    let format = MediaFormat::detect(path);
+    match format {
+        Some(format) if format.is_raw() => {
+            let data = fs::read(path)?;
+            let jpeg = largest_embedded_jpeg(&data)
+                .with_context(|| format!("No embedded preview found in {}", path.display()))?;
+            // the embedded preview usually lacks EXIF data, the orientation is in the RAW file
+            let orientation = read_exif(path.to_str().context("to_str failed")?)
+                .and_then(|exif| {
+                    exif.get_field(Tag::Orientation, In::PRIMARY)?
+                        .value
+                        .get_uint(0)
+                })
+                .and_then(|o| Orientation::from_exif(u8::try_from(o).ok()?));
+            decode_corrected(
+                ImageReader::with_format(Cursor::new(jpeg), ImageFormat::Jpeg).into_decoder()?,
+                orientation,
+            )
+        }
+        Some(MediaFormat::Heic | MediaFormat::Heif) => convert_heif(path),
+        _ => decode_corrected(
+            ImageReader::open(path)?
+                .with_guessed_format()?
+                .into_decoder()?,
+            None,
+        ),
+    }
+}
+
+// applies the orientation, from the image itself unless it is given, and converts the colors
+// of an embedded ICC profile to sRGB
+fn decode_corrected(
+    mut decoder: impl ImageDecoder,
+    orientation: Option<Orientation>,
+) -> Result<DynamicImage> {
+    let icc_profile = decoder.icc_profile().unwrap_or_else(|e| {
+        error!("Failed to read the ICC profile: {e}");
+        None
+    });
+    let orientation = match orientation {
+        Some(orientation) => orientation,
+        None => decoder.orientation()?,
+    };
+    let mut image = DynamicImage::from_decoder(decoder)?;
+    image.apply_orientation(orientation);
+    Ok(match icc_profile {
+        Some(icc_profile) => to_srgb(image, &icc_profile),
+        None => image,
+    })
+}
+
+// the image is returned as it is when the profile is not an RGB profile that can be converted
+fn to_srgb(image: DynamicImage, icc_profile: &[u8]) -> DynamicImage {
+    static SRGB: LazyLock<Box<Profile>> = LazyLock::new(|| {
+        let mut srgb = Profile::new_sRGB();
+        srgb.precache_output_transform();
+        srgb
+    });
+    let Some(transform) = Profile::new_from_slice(icc_profile, false)
+        .and_then(|profile| Transform::new(&profile, &SRGB, DataType::RGB8, Intent::Perceptual))
+    else {
+        error!("Unsupported ICC profile, the colors are not converted to sRGB");
+        return image;
+    };
+    let mut rgb = image.to_rgb8();
+    transform.apply(&mut rgb);
+    DynamicImage::ImageRgb8(rgb)
+}
+
+fn convert_heif(path: &Path) -> Result<DynamicImage> {
+    let Some(converter) = HEIF_CONVERTER.as_deref() else {
+        bail!("Cannot decode HEIF, set HEIF_CONVERTER or install heif-convert");
+    };
+    // the converter picks the output format from the extension
+    let output = tempfile::Builder::new()
+        .prefix("photomanager-heif-")
+        .suffix(".jpg")
+        .tempfile()?;
+    info!("Converting {} with {}", path.display(), converter);
+    let status = Command::new(converter)
+        .arg(path)
+        .arg(output.path())
+        .status();
+    let image = match status {
+        Ok(status) if status.success() => ImageReader::open(output.path())
+            .map_err(anyhow::Error::from)
+            .and_then(|reader| {
+                decode_corrected(reader.with_guessed_format()?.into_decoder()?, None)
+            }),
+        Ok(status) => Err(anyhow::anyhow!("{converter} failed with {status}")),
+        Err(e) => Err(anyhow::anyhow!("Failed to run {converter}: {e}")),
+    };
+    image.with_context(|| format!("Failed to convert {}", path.display()))
+}
+
+fn is_on_path(command: &str) -> bool {
+    env::var_os("PATH")
+        .is_some_and(|paths| env::split_paths(&paths).any(|dir| dir.join(command).is_file()))
+}
+
+/// RAW files embed one or more JPEG previews, the largest is usually the full size rendering
+/// of the camera
+fn largest_embedded_jpeg(data: &[u8]) -> Option<&[u8]> {
+    let mut largest: Option<&[u8]> = None;
+    let mut offset = 0;
+    while let Some(start) = data
+        .get(offset..)?
+        .windows(3)
+        .position(|w| w == [0xFF, 0xD8, 0xFF])
+        .map(|p| p + offset)
+    {
+        match jpeg_len(&data[start..]) {
+            Some(len) => {
+                let jpeg = &data[start..start + len];
+                if largest.is_none_or(|l| jpeg.len() > l.len()) {
+                    largest = Some(jpeg);
+                }
+                offset = start + len;
+            }
+            None => offset = start + 3,
+        }
+    }
+    largest
+}
+
+// the length of the baseline or progressive JPEG at the start of the data, found by walking its
+// segments. Lossless JPEG, which some RAW formats use for the sensor data, is skipped.
+fn jpeg_len(data: &[u8]) -> Option<usize> {
+    let mut i = 2;
+    loop {
+        if *data.get(i)? != 0xFF {
+            return None;
+        }
+        let marker = *data.get(i + 1)?;
+        match marker {
+            0xD9 => return Some(i + 2),
+            0xFF => {
+                i += 1;
+                continue;
+            }
+            0x01 | 0xD0..=0xD7 => {
+                i += 2;
+                continue;
+            }
+            0xC3 | 0xC5..=0xC7 | 0xCB | 0xCD..=0xCF => return None,
+            _ => {}
+        }
+        let len = usize::from(u16::from_be_bytes([*data.get(i + 2)?, *data.get(i + 3)?]));
+        i += 2 + len;
+        if marker == 0xDA {
+            // the entropy coded data runs up to the next marker that is not a stuffed 0xFF
+            // byte or a restart marker
+            loop {
+                let next = i + data.get(i..)?.iter().position(|b| *b == 0xFF)?;
+                match *data.get(next + 1)? {
+                    0x00 | 0xD0..=0xD7 => i = next + 2,
+                    0xFF => i = next + 1,
+                    _ => {
+                        i = next;
+                        break;
+                    }
+                }
+            }
+        }
+    }
+}
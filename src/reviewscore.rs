@@ -1,27 +1,251 @@
-use async_graphql::Enum;
+use anyhow::Context;
+use async_graphql::{
+    Enum, InputValueError, InputValueResult, Name, Scalar, ScalarType, SimpleObject, Value,
+};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::HashSet;
+use std::sync::{LazyLock, OnceLock};
+use std::{env, fmt, fs};
 
-#[derive(Debug, Enum, Copy, Clone, Eq, PartialEq)]
-pub enum ReviewScore {
-    Best,
-    Good,
-    Worst,
-    AlreadyReviewed,
+const ALREADY_REVIEWED_FOLDER: &str = "already_reviewed";
+
+/// A folder under the media root that reviewed photos are moved to
+#[derive(Debug, Clone, Deserialize, SimpleObject)]
+pub struct Bucket {
+    /// Identifies the bucket, this is the review score that clients send
+    pub name: String,
+    pub folder: String,
+    /// Position of the bucket in the list of buckets, ascending
+    pub order: i32,
+    /// Where photos are uploaded to after they have been moved to the bucket
+    pub upload: Option<UploadTarget>,
+}
+
+#[derive(Debug, Enum, Copy, Clone, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum UploadTarget {
+    GooglePhotos,
+}
+
+#[derive(Deserialize)]
+struct BucketsConfig {
+    buckets: Vec<Bucket>,
+}
+
+// the buckets are loaded once from the toml file at REVIEW_BUCKETS_CONFIG, e.g.
+//
+// [[buckets]]
+// name = "print"
+// folder = "001-print"
+// order = 1
+// upload = "googlePhotos"
+static BUCKETS: OnceLock<Vec<Bucket>> = OnceLock::new();
+
+/// Loads and validates the review buckets, so that the server fails at startup instead of at the
+/// first review when `REVIEW_BUCKETS_CONFIG` is invalid
+pub fn init_buckets() -> anyhow::Result<()> {
+    let buckets = buckets_from_env()?;
+    let _ = BUCKETS.set(buckets);
+    Ok(())
+}
+
+fn buckets_from_env() -> anyhow::Result<Vec<Bucket>> {
+    env::var("REVIEW_BUCKETS_CONFIG").map_or_else(
+        |_| Ok(default_buckets()),
+        |config_path| {
+            load_buckets(&config_path)
+                .with_context(|| format!("Failed to load review buckets from '{config_path}'"))
+        },
+    )
+}
+
+// without init_buckets, like in tests, the buckets are loaded on first use
+fn buckets() -> &'static [Bucket] {
+    BUCKETS.get_or_init(|| buckets_from_env().unwrap_or_else(|e| panic!("{e:#}")))
 }
+
+static ALREADY_REVIEWED: LazyLock<Bucket> = LazyLock::new(|| Bucket {
+    name: ALREADY_REVIEWED_FOLDER.into(),
+    folder: ALREADY_REVIEWED_FOLDER.into(),
+    order: i32::MAX,
+    upload: None,
+});
+
+fn default_buckets() -> Vec<Bucket> {
+    [
+        ("best", "001-best", Some(UploadTarget::GooglePhotos)),
+        ("good", "002-good", None),
+        ("worst", "003-worst", None),
+    ]
+    .into_iter()
+    .zip(1..)
+    .map(|((name, folder, upload), order)| Bucket {
+        name: name.into(),
+        folder: folder.into(),
+        order,
+        upload,
+    })
+    .collect()
+}
+
+fn load_buckets(config_path: &str) -> anyhow::Result<Vec<Bucket>> {
+    parse_buckets(&fs::read_to_string(config_path)?)
+}
+
+/// Parses and validates the buckets of a toml configuration, sorted by their order
+pub fn parse_buckets(config: &str) -> anyhow::Result<Vec<Bucket>> {
+    let mut buckets = toml::from_str::<BucketsConfig>(config)?.buckets;
+    if buckets.is_empty() {
+        anyhow::bail!("at least one bucket is required");
+    }
+    let mut names = HashSet::new();
+    let mut folders = HashSet::from([ALREADY_REVIEWED_FOLDER.to_string()]);
+    for bucket in &buckets {
+        // the names are GraphQL enum values in upper case
+        let is_graphql_name = bucket
+            .name
+            .chars()
+            .next()
+            .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+            && bucket
+                .name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if !is_graphql_name || bucket.name.starts_with("__") {
+            anyhow::bail!(
+                "bucket name '{}' must start with a letter or _ and only contain letters, digits and _",
+                bucket.name
+            );
+        }
+        if bucket.name.eq_ignore_ascii_case(ALREADY_REVIEWED_FOLDER) {
+            anyhow::bail!(
+                "'{ALREADY_REVIEWED_FOLDER}' is reserved for duplicates of reviewed photos"
+            );
+        }
+        if !names.insert(bucket.name.to_lowercase()) {
+            anyhow::bail!("duplicate bucket name '{}'", bucket.name);
+        }
+        if bucket.folder.is_empty()
+            || bucket.folder.starts_with('.')
+            || bucket.folder.contains('/')
+            || !folders.insert(bucket.folder.clone())
+        {
+            anyhow::bail!("invalid or duplicate bucket folder '{}'", bucket.folder);
+        }
+    }
+    buckets.sort_by_key(|b| b.order);
+    Ok(buckets)
+}
+
+/// The bucket that a photo is reviewed into. In GraphQL the score is the name of the bucket,
+/// both as enum value (`BEST`) and as string (`"best"`) are accepted.
+#[derive(Copy, Clone)]
+pub struct ReviewScore(&'static Bucket);
+
 impl ReviewScore {
+    /// Case-insensitive lookup of a configured bucket
+    #[must_use]
+    pub fn from_name(name: &str) -> Option<Self> {
+        buckets()
+            .iter()
+            .find(|b| b.name.eq_ignore_ascii_case(name))
+            .map(Self)
+    }
+
+    /// Photos that turn out to be a copy of a reviewed photo are moved to this bucket
+    #[must_use]
+    pub fn already_reviewed() -> Self {
+        Self(&ALREADY_REVIEWED)
+    }
+
+    #[must_use]
+    pub fn first() -> Self {
+        Self(&buckets()[0])
+    }
+
+    #[must_use]
+    pub fn last() -> Self {
+        Self(buckets().last().unwrap_or(&buckets()[0]))
+    }
+
+    /// The folder of the bucket
+    #[must_use]
+    pub fn as_str(&self) -> &'static str {
+        self.0.folder.as_str()
+    }
+
+    #[must_use]
+    pub fn name(&self) -> &'static str {
+        self.0.name.as_str()
+    }
+
+    /// The position of the bucket, better buckets come first
     #[must_use]
-    pub const fn as_str(&self) -> &'static str {
-        match self {
-            Self::Best => "001-best",
-            Self::Good => "002-good",
-            Self::Worst => "003-worst",
-            Self::AlreadyReviewed => "already_reviewed",
+    pub fn order(&self) -> i32 {
+        self.0.order
+    }
+
+    #[must_use]
+    pub fn upload_target(&self) -> Option<UploadTarget> {
+        self.0.upload
+    }
+}
+
+impl PartialEq for ReviewScore {
+    fn eq(&self, other: &Self) -> bool {
+        self.name() == other.name()
+    }
+}
+
+impl Eq for ReviewScore {}
+
+impl fmt::Debug for ReviewScore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[Scalar(name = "ReviewScore")]
+impl ScalarType for ReviewScore {
+    fn parse(value: Value) -> InputValueResult<Self> {
+        match &value {
+            Value::Enum(name) => Self::from_name(name.as_str()),
+            Value::String(name) => Self::from_name(name),
+            _ => None,
         }
+        .ok_or_else(|| InputValueError::custom(format!("unknown review score {value}")))
     }
+
+    fn to_value(&self) -> Value {
+        Value::Enum(Name::new(self.name().to_uppercase()))
+    }
+}
+
+impl Serialize for ReviewScore {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.name())
+    }
+}
+
+impl<'de> Deserialize<'de> for ReviewScore {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        if name == ALREADY_REVIEWED_FOLDER {
+            return Ok(Self::already_reviewed());
+        }
+        Self::from_name(&name)
+            .ok_or_else(|| serde::de::Error::custom(format!("unknown review bucket '{name}'")))
+    }
+}
+
+#[must_use]
+pub fn get_buckets() -> &'static [Bucket] {
+    buckets()
 }
 
 #[must_use]
 pub fn get_review_scores() -> Vec<ReviewScore> {
-    vec![ReviewScore::Best, ReviewScore::Good, ReviewScore::Worst]
+    buckets().iter().map(ReviewScore).collect()
 }
 
 #[must_use]
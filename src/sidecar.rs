@@ -0,0 +1,82 @@
+use crate::live_photo::find_motion_clip;
+use crate::media_format::{MediaFormat, find_raw_companion};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Extensions of the files that other applications keep next to a photo: XMP metadata, iOS
+/// edits, Google Takeout metadata, RawTherapee and DxO PhotoLab edits
+const SIDECAR_EXTENSIONS: [&str; 5] = ["xmp", "aae", "json", "pp3", "dop"];
+
+/// The sidecars of the photo, named either `<file name>.<ext>` like `IMG_1234.JPG.xmp` or
+/// `<file stem>.<ext>` like `IMG_1234.AAE`. A `<file stem>.<ext>` sidecar belongs to none of the
+/// photos when several photos share the file stem, like `IMG_1234.JPG` and `IMG_1234.HEIC`. The
+/// RAW file of a RAW+JPEG pair is part of the photo and does not count.
+pub fn find_sidecars(path: &Path) -> Vec<PathBuf> {
+    let Some(file_name) = path.file_name().and_then(|n| n.to_str()) else {
+        return vec![];
+    };
+    let owns_stem = photos_with_stem(path) <= 1;
+    SIDECAR_EXTENSIONS
+        .iter()
+        .flat_map(|e| [(*e).to_string(), e.to_ascii_uppercase()])
+        .flat_map(|e| {
+            [
+                Some(path.with_file_name(format!("{file_name}.{e}"))),
+                owns_stem.then(|| path.with_extension(e)),
+            ]
+        })
+        .flatten()
+        .filter(|sidecar| sidecar != path && sidecar.is_file())
+        .collect()
+}
+
+// the number of photos in the folder of the photo with its file stem, not counting RAW files
+fn photos_with_stem(path: &Path) -> usize {
+    let (Some(folder), Some(stem)) = (path.parent(), path.file_stem()) else {
+        return 0;
+    };
+    fs::read_dir(folder).map_or(0, |entries| {
+        entries
+            .filter_map(Result::ok)
+            .map(|entry| entry.path())
+            .filter(|other| {
+                other.file_stem() == Some(stem)
+                    && other
+                        .extension()
+                        .and_then(|e| e.to_str())
+                        .and_then(MediaFormat::from_extension)
+                        .is_some_and(|format| !format.is_raw())
+            })
+            .count()
+    })
+}
+
+/// The files that belong to the photo and move along with it: its sidecars, the RAW file of a
+/// RAW+JPEG pair with the sidecars of the RAW file and the motion clip of a Live Photo
+pub fn find_companions(path: &Path) -> Vec<PathBuf> {
+    let mut companions = find_sidecars(path);
+    if let Some(raw) = find_raw_companion(path) {
+        companions.extend(find_sidecars(&raw));
+        companions.push(raw);
+    }
+    companions.extend(find_motion_clip(path));
+    companions.sort();
+    companions.dedup();
+    companions
+}
+
+/// The name of the companion when its photo is named `photo_name` instead, so that a photo
+/// that gets a unique `-N` suffix keeps its companions, like `IMG_1234-1.JPG.xmp` for
+/// `IMG_1234-1.JPG`. A companion with an unrelated name, like a Live Photo clip that was paired
+/// by its content identifier, gets the file stem of the photo.
+pub fn companion_name(companion: &Path, photo: &Path, photo_name: &Path) -> Option<String> {
+    let name = companion.file_name()?.to_str()?;
+    if let Some(suffix) = name.strip_prefix(photo.file_name()?.to_str()?) {
+        return Some(format!("{}{suffix}", photo_name.file_name()?.to_str()?));
+    }
+    let stem = photo_name.file_stem()?.to_str()?;
+    match name.strip_prefix(photo.file_stem()?.to_str()?) {
+        Some(suffix) => Some(format!("{stem}{suffix}")),
+        None => Some(format!("{stem}.{}", companion.extension()?.to_str()?)),
+    }
+}
@@ -0,0 +1,131 @@
+use crate::reviewscore::ReviewScore;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::error;
+
+const UNDO_STACK_FILE_NAME: &str = "undo_stack.json";
+const MAX_UNDO_DEPTH: usize = 1000;
+
+/// A move that was actually performed on the file system, with full paths
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PerformedMove {
+    pub source: String,
+    pub destination: String,
+    /// Whether an identical photo was already at the destination, which stays there when the
+    /// move is reverted
+    #[serde(default)]
+    pub replaced: bool,
+    pub score: ReviewScore,
+    /// Files that were moved along with the photo, like sidecars and the RAW file of a RAW+JPEG
+    /// pair
+    #[serde(default)]
+    pub companions: Vec<MovedFile>,
+    /// Name of the user who reviewed the photo, unknown for reviews from before authentication
+    #[serde(default)]
+    pub reviewer: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MovedFile {
+    pub source: String,
+    pub destination: String,
+    /// Whether an identical file was already at the destination
+    #[serde(default)]
+    pub replaced: bool,
+}
+
+impl PerformedMove {
+    /// The move of the photo itself
+    pub fn photo(&self) -> MovedFile {
+        MovedFile {
+            source: self.source.clone(),
+            destination: self.destination.clone(),
+            replaced: self.replaced,
+        }
+    }
+}
+
+/// Undo and redo history of reviews, persisted in the state folder so that it survives restarts
+#[derive(Default, Serialize, Deserialize)]
+pub struct UndoStack {
+    #[serde(skip)]
+    path: PathBuf,
+    undo: Vec<PerformedMove>,
+    redo: Vec<PerformedMove>,
+}
+
+impl UndoStack {
+    pub fn load(state_dir: &Path) -> Self {
+        let path = state_dir.join(UNDO_STACK_FILE_NAME);
+        let mut stack = Self::read(&path).unwrap_or_else(|e| {
+            error!("Failed to load the undo stack, starting with an empty history: {e:#}");
+            Self::default()
+        });
+        stack.path = path;
+        stack
+    }
+
+    fn read(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        serde_json::from_slice::<Self>(&fs::read(path)?)
+            .with_context(|| format!("Failed to parse undo stack '{}'", path.display()))
+    }
+
+    /// Registers a new review. This clears the redo history.
+    pub fn push(&mut self, performed: PerformedMove) {
+        self.redo.clear();
+        self.push_undo(performed);
+    }
+
+    pub fn push_undo(&mut self, performed: PerformedMove) {
+        self.undo.push(performed);
+        if self.undo.len() > MAX_UNDO_DEPTH {
+            self.undo.remove(0);
+        }
+    }
+
+    pub fn push_redo(&mut self, performed: PerformedMove) {
+        self.redo.push(performed);
+    }
+
+    /// The most recent review that matches in the undo history
+    pub fn find_undo(&self, matches: impl Fn(&PerformedMove) -> bool) -> Option<&PerformedMove> {
+        self.undo.iter().rev().find(|performed| matches(performed))
+    }
+
+    /// Removes the most recent review that matches from the undo history
+    pub fn take_undo(&mut self, matches: impl Fn(&PerformedMove) -> bool) -> Option<PerformedMove> {
+        self.undo
+            .iter()
+            .rposition(matches)
+            .map(|index| self.undo.remove(index))
+    }
+
+    /// Removes the most recently undone review that matches from the redo history
+    pub fn take_redo(&mut self, matches: impl Fn(&PerformedMove) -> bool) -> Option<PerformedMove> {
+        self.redo
+            .iter()
+            .rposition(matches)
+            .map(|index| self.redo.remove(index))
+    }
+
+    // the history is a convenience on top of the moves that have already been performed, so a
+    // failure to persist it is logged instead of failing the review
+    pub fn save(&self) {
+        if let Err(e) = self.write() {
+            error!("Failed to save the undo stack: {e:#}");
+        }
+    }
+
+    fn write(&self) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&self.path, serde_json::to_vec(self)?)
+            .with_context(|| format!("Failed to write undo stack '{}'", self.path.display()))
+    }
+}
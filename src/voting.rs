@@ -0,0 +1,204 @@
+use crate::auth::Authenticator;
+use crate::reviewscore::ReviewScore;
+use anyhow::{Context, Result, bail};
+use async_graphql::SimpleObject;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::{env, fs};
+use tracing::error;
+
+const VOTES_FILE_NAME: &str = "votes.json";
+
+/// Decides the score of a photo from the votes of several reviewers. Once all voters have voted
+/// without the rule resolving, the score with the most votes wins, ties go to the bucket that
+/// comes first.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "rule", rename_all = "camelCase", deny_unknown_fields)]
+pub enum VotingRule {
+    /// A single vote for `score` decides, like "any Best wins"
+    #[serde(rename_all = "camelCase")]
+    AnyWins { score: ReviewScore, voters: usize },
+    /// More than half of the voters agree
+    Majority { voters: usize },
+    /// `votes` voters agree, like "two of three"
+    Quorum { votes: usize, voters: usize },
+}
+
+// the rule is loaded once from the toml file at VOTING_CONFIG, e.g.
+//
+// rule = "quorum"
+// votes = 2
+// voters = 3
+//
+// without it, the first review decides
+static VOTING_RULE: OnceLock<Option<VotingRule>> = OnceLock::new();
+
+/// Loads and validates the voting rule, so that the server fails at startup instead of at the
+/// first vote when `VOTING_CONFIG` is invalid. Votes are counted by user, so voting needs
+/// authentication.
+pub fn init_voting_rule(authenticator: &Authenticator) -> Result<()> {
+    let rule = rule_from_env()?;
+    if rule.is_some() && !authenticator.is_enabled() {
+        bail!("VOTING_CONFIG needs AUTH_CONFIG, as votes are counted by user");
+    }
+    let _ = VOTING_RULE.set(rule);
+    Ok(())
+}
+
+fn rule_from_env() -> Result<Option<VotingRule>> {
+    env::var("VOTING_CONFIG")
+        .ok()
+        .map(|config_path| {
+            load_rule(&config_path)
+                .with_context(|| format!("Failed to load the voting rule from '{config_path}'"))
+        })
+        .transpose()
+}
+
+fn load_rule(config_path: &str) -> Result<VotingRule> {
+    let rule = toml::from_str::<VotingRule>(&fs::read_to_string(config_path)?)?;
+    let (required, voters) = match &rule {
+        VotingRule::AnyWins { voters, .. } => (1, *voters),
+        VotingRule::Majority { voters } => (voters / 2 + 1, *voters),
+        VotingRule::Quorum { votes, voters } => (*votes, *voters),
+    };
+    if required == 0 || required > voters {
+        bail!("the rule needs between 1 and {voters} agreeing votes");
+    }
+    Ok(rule)
+}
+
+impl VotingRule {
+    /// The configured rule, `None` when photos are not voted on. Without init_voting_rule, like
+    /// in tests, the rule is loaded on first use.
+    pub fn configured() -> Option<&'static Self> {
+        VOTING_RULE
+            .get_or_init(|| rule_from_env().unwrap_or_else(|e| panic!("{e:#}")))
+            .as_ref()
+    }
+
+    /// The score that the votes decide on, if any
+    pub fn resolve(&self, votes: &[ReviewScore]) -> Option<ReviewScore> {
+        let count = |score: ReviewScore| votes.iter().filter(|v| **v == score).count();
+        let (decided, voters) = match self {
+            Self::AnyWins { score, voters } => (votes.contains(score).then_some(*score), voters),
+            Self::Majority { voters } => (
+                votes
+                    .iter()
+                    .copied()
+                    .find(|score| count(*score) * 2 > *voters),
+                voters,
+            ),
+            Self::Quorum {
+                votes: required,
+                voters,
+            } => (
+                votes
+                    .iter()
+                    .copied()
+                    .find(|score| count(*score) >= *required),
+                voters,
+            ),
+        };
+        decided.or_else(|| {
+            (votes.len() >= *voters)
+                .then(|| {
+                    votes
+                        .iter()
+                        .copied()
+                        .max_by_key(|score| (count(*score), std::cmp::Reverse(score.order())))
+                })
+                .flatten()
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, SimpleObject)]
+pub struct Vote {
+    pub voter: String,
+    pub score: ReviewScore,
+    pub voted_at: DateTime<Utc>,
+}
+
+/// The outcome of a vote on a photo
+#[derive(SimpleObject)]
+pub struct VoteResult {
+    /// All votes on the photo so far, including the one that resolved it
+    pub votes: Vec<Vote>,
+    /// The score that the votes resolved to, null while the vote is still open
+    pub resolved_score: Option<ReviewScore>,
+    /// Url of the photo in its bucket once the vote has been resolved
+    pub destination: Option<String>,
+    /// Why the vote failed
+    pub message: Option<String>,
+}
+
+/// The open votes on photos, persisted in the state folder until the voting rule resolves them
+#[derive(Default, Serialize, Deserialize)]
+pub struct PhotoVotes {
+    #[serde(skip)]
+    path: PathBuf,
+    /// The votes by path relative to the media root
+    photos: BTreeMap<String, Vec<Vote>>,
+}
+
+impl PhotoVotes {
+    pub fn load(state_dir: &Path) -> Self {
+        let path = state_dir.join(VOTES_FILE_NAME);
+        let mut votes = Self::read(&path).unwrap_or_else(|e| {
+            error!("Failed to load the votes, starting without open votes: {e:#}");
+            Self::default()
+        });
+        votes.path = path;
+        votes
+    }
+
+    fn read(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        serde_json::from_slice::<Self>(&fs::read(path)?)
+            .with_context(|| format!("Failed to parse votes '{}'", path.display()))
+    }
+
+    /// Casts the vote, replacing an earlier vote of the voter, and returns all votes on the photo
+    pub fn vote(&mut self, relative_path: &str, voter: &str, score: ReviewScore) -> &[Vote] {
+        let votes = self.photos.entry(relative_path.into()).or_default();
+        votes.retain(|vote| vote.voter != voter);
+        votes.push(Vote {
+            voter: voter.into(),
+            score,
+            voted_at: Utc::now(),
+        });
+        votes
+    }
+
+    pub fn get(&self, relative_path: &str) -> &[Vote] {
+        self.photos.get(relative_path).map_or(&[], Vec::as_slice)
+    }
+
+    /// Forgets the votes on the photo, returns whether there were any
+    pub fn remove(&mut self, relative_path: &str) -> bool {
+        self.photos.remove(relative_path).is_some()
+    }
+
+    // votes on photos that have been moved or deleted since are forgotten
+    pub fn save(&mut self, root_dir: &str) {
+        self.photos
+            .retain(|relative_path, _| Path::new(root_dir).join(relative_path).exists());
+        if let Err(e) = self.write() {
+            error!("Failed to save the votes: {e:#}");
+        }
+    }
+
+    fn write(&self) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&self.path, serde_json::to_vec(self)?)
+            .with_context(|| format!("Failed to write votes '{}'", self.path.display()))
+    }
+}
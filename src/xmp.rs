@@ -0,0 +1,227 @@
+use anyhow::{Context, Result, bail};
+use quick_xml::events::{BytesEnd, BytesStart, BytesText, Event};
+use quick_xml::{Reader, Writer};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const NS_XMP: &str = "http://ns.adobe.com/xap/1.0/";
+const NS_DC: &str = "http://purl.org/dc/elements/1.1/";
+
+const EMPTY_PACKET: &str = r#"<?xpacket begin="﻿" id="W5M0MpCehiHzreSzNTczkc9d"?>
+<x:xmpmeta xmlns:x="adobe:ns:meta/">
+ <rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+  <rdf:Description rdf:about=""/>
+ </rdf:RDF>
+</x:xmpmeta>
+<?xpacket end="w"?>
+"#;
+
+/// The review decisions that are shared with other photo managers like digiKam, darktable and
+/// Lightroom through XMP sidecars
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct XmpMetadata {
+    /// xmp:Rating, 0 to 5 stars
+    pub rating: Option<i32>,
+    /// xmp:Label, the color label
+    pub label: Option<String>,
+    /// dc:subject
+    pub tags: Vec<String>,
+}
+
+impl XmpMetadata {
+    /// Overrides the fields that are given
+    #[must_use]
+    pub fn merge(
+        self,
+        rating: Option<i32>,
+        label: Option<String>,
+        tags: Option<Vec<String>>,
+    ) -> Self {
+        Self {
+            rating: rating.or(self.rating),
+            label: label.or(self.label),
+            tags: tags.unwrap_or(self.tags),
+        }
+    }
+}
+
+/// Sidecars are written as `<image file name>.xmp` like darktable and digiKam do, the Lightroom
+/// style `<image file stem>.xmp` is read as well
+pub fn sidecar_path(image_path: &str) -> String {
+    format!("{image_path}.xmp")
+}
+
+pub fn find_sidecar(image_path: &str) -> Option<PathBuf> {
+    let path = Path::new(image_path);
+    [
+        PathBuf::from(sidecar_path(image_path)),
+        path.with_extension("xmp"),
+    ]
+    .into_iter()
+    .find(|p| p.exists())
+}
+
+pub fn read_sidecar(image_path: &str) -> Result<XmpMetadata> {
+    find_sidecar(image_path).map_or_else(
+        || Ok(XmpMetadata::default()),
+        |sidecar| {
+            parse(&fs::read_to_string(&sidecar)?)
+                .with_context(|| format!("Failed to parse XMP sidecar {}", sidecar.display()))
+        },
+    )
+}
+
+/// Writes the metadata to the sidecar of the image. An existing sidecar is updated in place so
+/// that data of other applications, like darktable's edit history, is preserved.
+pub fn write_sidecar(image_path: &str, metadata: &XmpMetadata) -> Result<PathBuf> {
+    let sidecar = find_sidecar(image_path).unwrap_or_else(|| sidecar_path(image_path).into());
+    let existing = if sidecar.exists() {
+        fs::read_to_string(&sidecar)?
+    } else {
+        EMPTY_PACKET.into()
+    };
+    fs::write(&sidecar, update(&existing, metadata)?)
+        .with_context(|| format!("Failed to write XMP sidecar {}", sidecar.display()))?;
+    Ok(sidecar)
+}
+
+fn parse(xml: &str) -> Result<XmpMetadata> {
+    let mut reader = Reader::from_str(xml);
+    let mut metadata = XmpMetadata::default();
+    // the element whose text content is being read
+    let mut current: Vec<u8> = vec![];
+    let mut in_subject = false;
+
+    loop {
+        match reader.read_event()? {
+            Event::Start(e) | Event::Empty(e) if e.name().as_ref() == b"rdf:Description" => {
+                for attr in e.attributes().flatten() {
+                    match attr.key.as_ref() {
+                        b"xmp:Rating" => metadata.rating = attr.unescape_value()?.parse().ok(),
+                        b"xmp:Label" => metadata.label = Some(attr.unescape_value()?.into()),
+                        _ => {}
+                    }
+                }
+            }
+            Event::Start(e) => {
+                if e.name().as_ref() == b"dc:subject" {
+                    in_subject = true;
+                }
+                current = e.name().as_ref().to_vec();
+            }
+            Event::Text(t) => {
+                let text = t.unescape()?;
+                match current.as_slice() {
+                    b"xmp:Rating" => metadata.rating = text.trim().parse().ok(),
+                    b"xmp:Label" => metadata.label = Some(text.trim().into()),
+                    b"rdf:li" if in_subject => metadata.tags.push(text.trim().into()),
+                    _ => {}
+                }
+            }
+            Event::End(e) => {
+                if e.name().as_ref() == b"dc:subject" {
+                    in_subject = false;
+                }
+                current.clear();
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(metadata)
+}
+
+// copies the xml, replacing rating, label and subject in the first rdf:Description
+fn update(xml: &str, metadata: &XmpMetadata) -> Result<Vec<u8>> {
+    let mut reader = Reader::from_str(xml);
+    let mut writer = Writer::new(Vec::new());
+    let mut updated = false;
+    // depth of the element that is being dropped because it is replaced
+    let mut skip_depth = 0usize;
+
+    loop {
+        let event = reader.read_event()?;
+        if skip_depth > 0 {
+            match event {
+                Event::Start(_) => skip_depth += 1,
+                Event::End(_) => skip_depth -= 1,
+                Event::Eof => bail!("unexpected end of XMP packet"),
+                _ => {}
+            }
+            continue;
+        }
+        match event {
+            Event::Start(e) if !updated && e.name().as_ref() == b"rdf:Description" => {
+                writer.write_event(Event::Start(description_start(&e, metadata)?))?;
+                write_subject(&mut writer, &metadata.tags)?;
+                updated = true;
+            }
+            Event::Empty(e) if !updated && e.name().as_ref() == b"rdf:Description" => {
+                writer.write_event(Event::Start(description_start(&e, metadata)?))?;
+                write_subject(&mut writer, &metadata.tags)?;
+                writer.write_event(Event::End(BytesEnd::new("rdf:Description")))?;
+                updated = true;
+            }
+            Event::Start(e) if is_replaced_element(&e) => skip_depth = 1,
+            Event::Empty(e) if is_replaced_element(&e) => {}
+            Event::Eof => break,
+            e => writer.write_event(e)?,
+        }
+    }
+    if !updated {
+        bail!("no rdf:Description found in XMP packet");
+    }
+    Ok(writer.into_inner())
+}
+
+fn is_replaced_element(e: &BytesStart) -> bool {
+    matches!(
+        e.name().as_ref(),
+        b"xmp:Rating" | b"xmp:Label" | b"dc:subject"
+    )
+}
+
+fn description_start<'a>(e: &'a BytesStart, metadata: &XmpMetadata) -> Result<BytesStart<'a>> {
+    let mut start = BytesStart::new("rdf:Description");
+    let mut has_xmp_ns = false;
+    let mut has_dc_ns = false;
+    for attr in e.attributes() {
+        let attr = attr?;
+        match attr.key.as_ref() {
+            b"xmp:Rating" | b"xmp:Label" => continue,
+            b"xmlns:xmp" => has_xmp_ns = true,
+            b"xmlns:dc" => has_dc_ns = true,
+            _ => {}
+        }
+        start.push_attribute(attr);
+    }
+    if !has_xmp_ns {
+        start.push_attribute(("xmlns:xmp", NS_XMP));
+    }
+    if !has_dc_ns {
+        start.push_attribute(("xmlns:dc", NS_DC));
+    }
+    if let Some(rating) = metadata.rating {
+        start.push_attribute(("xmp:Rating", rating.to_string().as_str()));
+    }
+    if let Some(label) = &metadata.label {
+        start.push_attribute(("xmp:Label", label.as_str()));
+    }
+    Ok(start)
+}
+
+fn write_subject(writer: &mut Writer<Vec<u8>>, tags: &[String]) -> Result<()> {
+    if tags.is_empty() {
+        return Ok(());
+    }
+    writer.write_event(Event::Start(BytesStart::new("dc:subject")))?;
+    writer.write_event(Event::Start(BytesStart::new("rdf:Bag")))?;
+    for tag in tags {
+        writer.write_event(Event::Start(BytesStart::new("rdf:li")))?;
+        writer.write_event(Event::Text(BytesText::new(tag)))?;
+        writer.write_event(Event::End(BytesEnd::new("rdf:li")))?;
+    }
+    writer.write_event(Event::End(BytesEnd::new("rdf:Bag")))?;
+    writer.write_event(Event::End(BytesEnd::new("dc:subject")))?;
+    Ok(())
+}
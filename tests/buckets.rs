@@ -0,0 +1,95 @@
+use anyhow::Result;
+use async_graphql::value;
+use std::path::PathBuf;
+
+// the review buckets are loaded once per process, so tests with a custom configuration live in
+// their own test binary
+#[tokio::test]
+async fn test_review_with_configured_buckets() -> Result<()> {
+    let media_dir = std::env::temp_dir()
+        .join(format!(
+            "photomanager-buckets-{}",
+            fastrand::u32(1..100_000)
+        ))
+        .to_str()
+        .unwrap()
+        .to_string();
+    let config_path = PathBuf::from(&media_dir).join("buckets.toml");
+    std::fs::create_dir_all(PathBuf::from(&media_dir).join("albumX"))?;
+    std::fs::write(
+        &config_path,
+        r#"
+[[buckets]]
+name = "delete"
+folder = "099-delete"
+order = 99
+
+[[buckets]]
+name = "print"
+folder = "010-print"
+order = 10
+upload = "googlePhotos"
+"#,
+    )?;
+    std::fs::write(PathBuf::from(&media_dir).join("albumX/photo.jpg"), "i")?;
+    unsafe {
+        std::env::set_var("REVIEW_BUCKETS_CONFIG", &config_path);
+        std::env::set_var("PUBLIC_URL", "http://integration-test");
+    };
+
+    let schema = photomanagerlib::model::new_schema(Some(&media_dir));
+    let data = schema
+        .execute("{ reviewBuckets { name folder order upload } }")
+        .await
+        .into_result()
+        .unwrap()
+        .data;
+    assert_eq!(
+        data,
+        value!({
+            "reviewBuckets": [
+                { "name": "print", "folder": "010-print", "order": 10, "upload": "GOOGLE_PHOTOS" },
+                { "name": "delete", "folder": "099-delete", "order": 99, "upload": null }
+            ]
+        })
+    );
+
+    let data = schema
+        .execute("mutation { reviewPhoto(path: \"/media/albumX/photo.jpg\", score: \"delete\") { success } }")
+        .await
+        .into_result()
+        .unwrap()
+        .data;
+    assert_eq!(data, value!({ "reviewPhoto": { "success": true } }));
+    assert!(
+        PathBuf::from(&media_dir)
+            .join("099-delete/albumX/photo.jpg")
+            .exists()
+    );
+
+    let errors = schema
+        .execute(
+            "mutation { reviewPhoto(path: \"/media/albumX/photo.jpg\", score: BEST) { success } }",
+        )
+        .await
+        .errors;
+    assert_eq!(errors.len(), 1, "BEST is not a configured bucket");
+    Ok(())
+}
+
+#[test]
+fn test_invalid_bucket_names_are_rejected() {
+    for name in ["best photos", "1st", "__best", "Already_Reviewed"] {
+        let config = format!("[[buckets]]\nname = \"{name}\"\nfolder = \"001-best\"\norder = 1\n");
+        assert!(
+            photomanagerlib::reviewscore::parse_buckets(&config).is_err(),
+            "{name} is not a valid bucket name"
+        );
+    }
+    assert!(
+        photomanagerlib::reviewscore::parse_buckets(
+            "[[buckets]]\nname = \"print_2\"\nfolder = \"001-print\"\norder = 1\n"
+        )
+        .is_ok()
+    );
+}
@@ -0,0 +1,44 @@
+// helpers shared by the test binaries, not every binary uses all of them
+#![allow(dead_code)]
+
+use anyhow::Result;
+use async_graphql::{Request, Value};
+use photomanagerlib::auth::{Role, User};
+use photomanagerlib::model::ServiceSchema;
+use std::path::PathBuf;
+
+/// A new media root under the temp folder, with the files at the paths relative to it, which
+/// contain their path
+pub fn temp_media_root(name: &str, files: &[&str]) -> Result<String> {
+    let media_dir = std::env::temp_dir()
+        .join(format!("photomanager-{name}-{}", fastrand::u64(..)))
+        .to_str()
+        .unwrap()
+        .to_string();
+    std::fs::create_dir_all(&media_dir)?;
+    for file in files {
+        let path = PathBuf::from(&media_dir).join(file);
+        std::fs::create_dir_all(path.parent().unwrap())?;
+        std::fs::write(path, file)?;
+    }
+    Ok(media_dir)
+}
+
+/// A user with access to all folders
+pub fn user(name: &str, role: Role) -> User {
+    User {
+        name: name.into(),
+        role,
+        folders: None,
+    }
+}
+
+/// Executes the query as the user and returns its data, the query must not fail
+pub async fn execute(schema: &ServiceSchema, query: &str, user: User) -> Value {
+    schema
+        .execute(Request::new(query).data(user))
+        .await
+        .into_result()
+        .unwrap()
+        .data
+}
@@ -0,0 +1,66 @@
+use anyhow::Result;
+use photomanagerlib::events::Events;
+use photomanagerlib::folder_watcher::PendingFolders;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+#[test]
+fn test_pending_folders_follow_changes() -> Result<()> {
+    let media_dir =
+        std::env::temp_dir().join(format!("photomanager-watcher-{}", fastrand::u64(..)));
+    std::fs::create_dir_all(media_dir.join("albumX"))?;
+    std::fs::write(media_dir.join("albumX/1.jpg"), "1")?;
+    std::fs::create_dir_all(media_dir.join("empty"))?;
+
+    let pending = PendingFolders::watch(
+        media_dir.to_str().unwrap(),
+        Duration::from_millis(100),
+        Events::default(),
+        |_| 0,
+    )?;
+    assert_eq!(pending.folders(), vec![media_dir.join("albumX")]);
+
+    // a burst of synced photos, a reviewed photo and a folder that is moved in with its photos
+    for i in 0..20 {
+        std::fs::write(media_dir.join(format!("empty/{i}.jpg")), "photo")?;
+    }
+    std::fs::create_dir_all(media_dir.join("002-good/albumX"))?;
+    std::fs::rename(
+        media_dir.join("albumX/1.jpg"),
+        media_dir.join("002-good/albumX/1.jpg"),
+    )?;
+    let synced = std::env::temp_dir().join(format!("photomanager-synced-{}", fastrand::u64(..)));
+    std::fs::create_dir_all(synced.join("day1"))?;
+    std::fs::write(synced.join("day1/2.jpg"), "2")?;
+    // only the buckets at the top of the media root are ignored
+    std::fs::create_dir_all(synced.join("002-good"))?;
+    std::fs::write(synced.join("002-good/3.jpg"), "3")?;
+    std::fs::rename(&synced, media_dir.join("trip"))?;
+
+    let expected: Vec<PathBuf> = vec![
+        media_dir.join("empty"),
+        media_dir.join("trip/002-good"),
+        media_dir.join("trip/day1"),
+    ];
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while pending.folders() != expected && Instant::now() < deadline {
+        std::thread::sleep(Duration::from_millis(50));
+    }
+    assert_eq!(pending.folders(), expected);
+
+    // the folders that were moved in are watched with their subfolders
+    std::fs::create_dir_all(media_dir.join("trip/day2"))?;
+    std::fs::write(media_dir.join("trip/day2/4.jpg"), "4")?;
+    let expected: Vec<PathBuf> = vec![
+        media_dir.join("empty"),
+        media_dir.join("trip/002-good"),
+        media_dir.join("trip/day1"),
+        media_dir.join("trip/day2"),
+    ];
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while pending.folders() != expected && Instant::now() < deadline {
+        std::thread::sleep(Duration::from_millis(50));
+    }
+    assert_eq!(pending.folders(), expected);
+    Ok(())
+}
@@ -0,0 +1,54 @@
+use anyhow::Result;
+use photomanagerlib::preview::{PreviewCache, PreviewSize};
+use std::os::unix::fs::PermissionsExt;
+use std::path::PathBuf;
+
+// the HEIF converter is looked up once per process, so this test lives in its own test binary
+#[test]
+fn test_heic_preview_is_converted_through_a_temporary_file() -> Result<()> {
+    let media_dir = std::env::temp_dir()
+        .join(format!("photomanager-heif-test-{}", fastrand::u64(..)))
+        .to_str()
+        .unwrap()
+        .to_string();
+    let root = PathBuf::from(&media_dir);
+    std::fs::create_dir_all(root.join("albumX"))?;
+    let mut heic = vec![0, 0, 0, 24];
+    heic.extend(b"ftypheic");
+    heic.extend([0, 0, 0, 0]);
+    heic.extend(b"mif1heic");
+    std::fs::write(root.join("albumX/IMG_0001.heic"), heic)?;
+
+    // stands in for heif-convert, it writes a JPEG to the output and logs the output path
+    let converted = root.join("converted.jpg");
+    image::RgbImage::from_pixel(30, 20, image::Rgb([200, 100, 50])).save(&converted)?;
+    let log = root.join("converter.log");
+    let converter = root.join("fake-heif-convert");
+    std::fs::write(
+        &converter,
+        format!(
+            "#!/bin/sh\ncp '{}' \"$2\"\necho \"$2\" > '{}'\n",
+            converted.display(),
+            log.display()
+        ),
+    )?;
+    std::fs::set_permissions(&converter, std::fs::Permissions::from_mode(0o755))?;
+    unsafe { std::env::set_var("HEIF_CONVERTER", &converter) };
+
+    let cache = PreviewCache::new(&media_dir, u64::MAX);
+    let preview = cache.get("albumX/IMG_0001.heic", PreviewSize::Full)?;
+    assert_eq!(image::image_dimensions(&preview)?, (30, 20));
+
+    let output = PathBuf::from(std::fs::read_to_string(&log)?.trim());
+    assert_eq!(output.extension().unwrap(), "jpg");
+    assert!(
+        output
+            .file_name()
+            .unwrap()
+            .to_string_lossy()
+            .starts_with("photomanager-heif-")
+    );
+    // the converted file is removed once it is decoded
+    assert!(!output.exists());
+    Ok(())
+}
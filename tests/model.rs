@@ -10,7 +10,7 @@ async fn test_get_photos() -> Result<()> {
     let media_dir = init_env()?;
     write_reviewed_image(
         &media_dir,
-        photomanagerlib::reviewscore::ReviewScore::Best,
+        review_score("best"),
         "albumX",
         "best-photo.jpg",
         "i",
@@ -18,7 +18,7 @@ async fn test_get_photos() -> Result<()> {
     // should not be listed because it has been reviewed before
     let unreviewed_best_photo_path = write_image(&media_dir, "albumX", "best-photo.jpg", "i")?;
 
-    write_image(&media_dir, "albumX", "123.jpg", "i")?;
+    write_image(&media_dir, "albumX", "123.jpg", "j")?;
 
     let data = photomanagerlib::model::new_schema(Some(&media_dir))
         .execute(
@@ -72,7 +72,7 @@ async fn test_undo() -> Result<()> {
     let media_dir = init_env()?;
     let best_photo_reviewed_path = write_reviewed_image(
         &media_dir,
-        photomanagerlib::reviewscore::ReviewScore::Best,
+        review_score("best"),
         "albumX",
         "best-photo.jpg",
         "i",
@@ -142,7 +142,7 @@ mutation {
 
     assert!(
         PathBuf::from(&media_dir)
-            .join(photomanagerlib::reviewscore::ReviewScore::Good.as_str())
+            .join(review_score("good").as_str())
             .join("albumX")
             .join("good-photo.jpg")
             .exists()
@@ -155,6 +155,1403 @@ mutation {
     Ok(())
 }
 
+#[tokio::test]
+async fn test_review_history() -> Result<()> {
+    let media_dir = init_env()?;
+    write_image(&media_dir, "albumX", "photo.jpg", "i")?;
+    let schema = photomanagerlib::model::new_schema(Some(&media_dir));
+    for mutation in [
+        "mutation { reviewPhoto(path: \"/media/albumX/photo.jpg\", score: BEST) { success } }",
+        "mutation { undo(path: \"/media/albumX/photo.jpg\", score: BEST) { success } }",
+    ] {
+        schema.execute(mutation).await.into_result().unwrap();
+    }
+
+    let data = schema
+        .execute(
+            "
+{
+  reviewHistory(limit: 1) {
+    success
+    output {
+      entries {
+        action
+        source
+        destination
+        score
+      }
+      nextCursor
+    }
+  }
+}
+",
+        )
+        .await
+        .into_result()
+        .unwrap()
+        .data;
+
+    assert_eq!(
+        data,
+        value!({
+            "reviewHistory": {
+                "success": true,
+                "output": {
+                    "entries": [
+                        {
+                            "action": "UNDO",
+                            "source": "001-best/albumX/photo.jpg",
+                            "destination": "albumX/photo.jpg",
+                            "score": "BEST"
+                        }
+                    ],
+                    "nextCursor": 2
+                }
+            }
+        })
+    );
+
+    for limit in [0, 201] {
+        let response = schema
+            .execute(format!(
+                "{{ reviewHistory(limit: {limit}) {{ output {{ nextCursor }} }} }}"
+            ))
+            .await;
+        assert!(
+            !response.errors.is_empty(),
+            "limit {limit} must be rejected"
+        );
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_review_history_pages_through_a_long_journal() -> Result<()> {
+    let media_dir = init_env()?;
+    let state_dir = PathBuf::from(&media_dir).join(".photomanager");
+    std::fs::create_dir_all(&state_dir)?;
+    // longer than the chunks that the journal is read in from the end
+    let journal = (1..=1000)
+        .map(|id| {
+            format!(
+                "{{\"id\":{id},\"action\":\"review\",\"source\":\"albumX/{id}.jpg\",\"destination\":\"002-good/albumX/{id}.jpg\",\"score\":\"good\",\"timestamp\":\"2026-10-18T10:57:37Z\"}}\n"
+            )
+        })
+        .collect::<String>();
+    std::fs::write(state_dir.join("journal.jsonl"), journal)?;
+    let schema = photomanagerlib::model::new_schema(Some(&media_dir));
+
+    let mut ids = vec![];
+    let mut cursor = String::new();
+    loop {
+        let data = schema
+            .execute(format!(
+                "{{ reviewHistory(limit: 200{cursor}) {{ output {{ entries {{ id }} nextCursor }} }} }}"
+            ))
+            .await
+            .into_result()
+            .unwrap()
+            .data
+            .into_json()?;
+        let output = &data["reviewHistory"]["output"];
+        ids.extend(
+            output["entries"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|entry| entry["id"].as_u64().unwrap()),
+        );
+        match output["nextCursor"].as_u64() {
+            Some(next) => cursor = format!(", cursor: {next}"),
+            None => break,
+        }
+    }
+    assert_eq!(ids, (1..=1000).rev().collect::<Vec<_>>());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_undo_last_and_redo_renamed_photo() -> Result<()> {
+    let media_dir = init_env()?;
+    let existing_path = write_reviewed_image(
+        &media_dir,
+        review_score("good"),
+        "albumX",
+        "photo.jpg",
+        "other contents",
+    )?;
+    let photo_path = write_image(&media_dir, "albumX", "photo.jpg", "i")?;
+    let renamed_path = existing_path.with_file_name("photo-1.jpg");
+    let schema = photomanagerlib::model::new_schema(Some(&media_dir));
+    schema
+        .execute(
+            "mutation { reviewPhoto(path: \"/media/albumX/photo.jpg\", score: GOOD) { success } }",
+        )
+        .await
+        .into_result()
+        .unwrap();
+    assert!(renamed_path.exists());
+
+    let data = schema
+        .execute("mutation { undoLast { success output } }")
+        .await
+        .into_result()
+        .unwrap()
+        .data;
+    assert_eq!(
+        data,
+        value!({
+            "undoLast": {
+                "success": true,
+                "output": "/media/albumX/photo.jpg"
+            }
+        })
+    );
+    assert!(photo_path.exists() && !renamed_path.exists());
+    assert!(
+        existing_path.exists(),
+        "the photo that was reviewed before should not be touched"
+    );
+
+    let data = schema
+        .execute("mutation { redo { success output } }")
+        .await
+        .into_result()
+        .unwrap()
+        .data;
+    assert_eq!(
+        data,
+        value!({
+            "redo": {
+                "success": true,
+                "output": "/media/albumX/photo.jpg"
+            }
+        })
+    );
+    assert!(!photo_path.exists() && renamed_path.exists());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_get_photos_excludes_duplicate_reviewed_in_other_album() -> Result<()> {
+    let media_dir = init_env()?;
+    write_reviewed_image(
+        &media_dir,
+        review_score("worst"),
+        "albumY",
+        "original.jpg",
+        "same contents",
+    )?;
+    let duplicate_path = write_image(&media_dir, "albumX", "copy.jpg", "same contents")?;
+    write_image(&media_dir, "albumX", "other.jpg", "other contents")?;
+
+    let data = photomanagerlib::model::new_schema(Some(&media_dir))
+        .execute("{ photosToReview { output { photos { url } } } }")
+        .await
+        .into_result()
+        .unwrap()
+        .data;
+
+    assert_eq!(
+        data,
+        value!({
+            "photosToReview": {
+                "output": {
+                    "photos": [ { "url": "/media/albumX/other.jpg" } ]
+                }
+            }
+        })
+    );
+    assert!(!duplicate_path.exists());
+    assert!(
+        PathBuf::from(&media_dir)
+            .join(photomanagerlib::reviewscore::ReviewScore::already_reviewed().as_str())
+            .join("albumX")
+            .join("copy.jpg")
+            .exists()
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_get_photos_excludes_duplicate_reviewed_since_the_start() -> Result<()> {
+    let media_dir = init_env()?;
+    write_image(&media_dir, "albumX", "1.jpg", "one")?;
+    write_image(&media_dir, "albumX", "2.jpg", "two")?;
+    let schema = photomanagerlib::model::new_schema(Some(&media_dir));
+    let photos_to_review = async |folder: &str| {
+        schema
+            .execute(format!(
+                "{{ photosToReview(folder: \"{folder}\") {{ output {{ photos {{ url }} }} }} }}"
+            ))
+            .await
+            .into_result()
+            .unwrap()
+            .data
+    };
+    assert_eq!(
+        photos_to_review("albumX").await,
+        value!({
+            "photosToReview": {
+                "output": {
+                    "photos": [ { "url": "/media/albumX/1.jpg" }, { "url": "/media/albumX/2.jpg" } ]
+                }
+            }
+        })
+    );
+
+    // the reviewed photos are known without walking the buckets again, and undone reviews are
+    // forgotten
+    for mutation in [
+        "mutation { reviewPhoto(path: \"/media/albumX/1.jpg\", score: GOOD) { success } }",
+        "mutation { reviewPhoto(path: \"/media/albumX/2.jpg\", score: GOOD) { success } }",
+        "mutation { undo(path: \"/media/albumX/2.jpg\", score: GOOD) { success } }",
+    ] {
+        let response = schema.execute(mutation).await;
+        assert!(response.errors.is_empty(), "{mutation}");
+    }
+    write_image(&media_dir, "albumY", "copy-1.jpg", "one")?;
+    write_image(&media_dir, "albumY", "copy-2.jpg", "two")?;
+    assert_eq!(
+        photos_to_review("albumY").await,
+        value!({
+            "photosToReview": {
+                "output": {
+                    "photos": [ { "url": "/media/albumY/copy-2.jpg" } ]
+                }
+            }
+        })
+    );
+    assert!(
+        PathBuf::from(&media_dir)
+            .join(photomanagerlib::reviewscore::ReviewScore::already_reviewed().as_str())
+            .join("albumY")
+            .join("copy-1.jpg")
+            .exists()
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_get_photos_groups_bursts_and_review_group() -> Result<()> {
+    let media_dir = init_env()?;
+    write_gradient_png(&media_dir, "albumX", "burst-1.png", 0)?;
+    write_gradient_png(&media_dir, "albumX", "burst-2.png", 10)?;
+    let other_path = PathBuf::from(&media_dir).join("albumX").join("other.png");
+    image::GrayImage::from_fn(64, 48, |x, _| {
+        image::Luma([255 - u8::try_from(x * 3).unwrap()])
+    })
+    .save(&other_path)?;
+
+    let schema = photomanagerlib::model::new_schema(Some(&media_dir));
+    let data = schema
+        .execute("{ photosToReview { output { groups { photos { url } } } } }")
+        .await
+        .into_result()
+        .unwrap()
+        .data;
+    assert_eq!(
+        data,
+        value!({
+            "photosToReview": {
+                "output": {
+                    "groups": [
+                        { "photos": [ { "url": "/media/albumX/burst-1.png" }, { "url": "/media/albumX/burst-2.png" } ] },
+                        { "photos": [ { "url": "/media/albumX/other.png" } ] }
+                    ]
+                }
+            }
+        })
+    );
+
+    let data = schema
+        .execute(
+            "
+mutation {
+  reviewGroup(keep: \"/media/albumX/burst-2.png\", others: [\"/media/albumX/burst-1.png\"]) {
+    success
+  }
+}
+",
+        )
+        .await
+        .into_result()
+        .unwrap()
+        .data;
+    assert_eq!(data, value!({ "reviewGroup": { "success": true } }));
+    for (score, file_name) in [
+        (review_score("best"), "burst-2.png"),
+        (review_score("worst"), "burst-1.png"),
+    ] {
+        assert!(
+            PathBuf::from(&media_dir)
+                .join(score.as_str())
+                .join("albumX")
+                .join(file_name)
+                .exists()
+        );
+    }
+
+    // a group with a photo that cannot be reviewed leaves all photos of the group in place
+    let data = schema
+        .execute(
+            "
+mutation {
+  reviewGroup(keep: \"/media/albumX/other.png\", others: [\"/media/albumX/missing.png\"]) {
+    success
+  }
+}
+",
+        )
+        .await
+        .into_result()
+        .unwrap()
+        .data;
+    assert_eq!(data, value!({ "reviewGroup": { "success": false } }));
+    assert!(PathBuf::from(&media_dir).join("albumX/other.png").exists());
+    Ok(())
+}
+
+fn write_gradient_png(folder: &str, album: &str, file_name: &str, brightness: u8) -> Result<()> {
+    let path = PathBuf::from(folder).join(album).join(file_name);
+    std::fs::create_dir_all(path.parent().unwrap())?;
+    image::GrayImage::from_fn(64, 48, |x, _| {
+        image::Luma([u8::try_from(x * 3).unwrap() + brightness])
+    })
+    .save(path)?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rate_photo_writes_xmp_sidecar() -> Result<()> {
+    let media_dir = init_env()?;
+    write_image(&media_dir, "albumX", "photo.jpg", "i")?;
+    let sidecar_path = write_image(
+        &media_dir,
+        "albumX",
+        "photo.jpg.xmp",
+        r#"<x:xmpmeta xmlns:x="adobe:ns:meta/">
+ <rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+  <rdf:Description rdf:about="" xmlns:xmp="http://ns.adobe.com/xap/1.0/"
+    xmlns:darktable="http://darktable.sf.net/" xmp:Rating="1" darktable:xmp_version="5">
+   <darktable:history><rdf:Seq/></darktable:history>
+  </rdf:Description>
+ </rdf:RDF>
+</x:xmpmeta>"#,
+    )?;
+    let schema = photomanagerlib::model::new_schema(Some(&media_dir));
+    schema
+        .execute(
+            "mutation { ratePhoto(path: \"/media/albumX/photo.jpg\", stars: 4, label: \"Green\", tags: [\"family\", \"beach\"]) { success } }",
+        )
+        .await
+        .into_result()
+        .unwrap();
+
+    let data = schema
+        .execute("{ photosToReview { output { photos { url rating label tags } } } }")
+        .await
+        .into_result()
+        .unwrap()
+        .data;
+    assert_eq!(
+        data,
+        value!({
+            "photosToReview": {
+                "output": {
+                    "photos": [ {
+                        "url": "/media/albumX/photo.jpg",
+                        "rating": 4,
+                        "label": "Green",
+                        "tags": ["family", "beach"]
+                    } ]
+                }
+            }
+        })
+    );
+    let sidecar = std::fs::read_to_string(&sidecar_path)?;
+    assert!(
+        sidecar.contains("darktable:history"),
+        "data of other applications should be preserved"
+    );
+
+    let data = schema
+        .execute(
+            "mutation { ratePhoto(path: \"/media/albumX/photo.jpg\", stars: 5, score: GOOD) { success } }",
+        )
+        .await
+        .into_result()
+        .unwrap()
+        .data;
+    assert_eq!(data, value!({ "ratePhoto": { "success": true } }));
+    assert!(!sidecar_path.exists());
+    let moved_sidecar = std::fs::read_to_string(
+        PathBuf::from(&media_dir)
+            .join(review_score("good").as_str())
+            .join("albumX")
+            .join("photo.jpg.xmp"),
+    )?;
+    assert!(moved_sidecar.contains(r#"xmp:Rating="5""#));
+    assert!(moved_sidecar.contains("<rdf:li>beach</rdf:li>"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_get_photos_paginated() -> Result<()> {
+    let media_dir = init_env()?;
+    for (file_name, contents) in [("a.jpg", "a"), ("b.jpg", "b"), ("c.jpg", "c")] {
+        write_image(&media_dir, "albumX", file_name, contents)?;
+    }
+    let schema = photomanagerlib::model::new_schema(Some(&media_dir));
+    let query = |after: &str| {
+        format!(
+            "{{ photosToReview(first: 2{after}) {{ output {{ photos {{ url }} pageInfo {{ hasNextPage endCursor }} folderImageCount }} }} }}"
+        )
+    };
+
+    let data = schema
+        .execute(query(""))
+        .await
+        .into_result()
+        .unwrap()
+        .data
+        .into_json()?;
+    let output = &data["photosToReview"]["output"];
+    assert_eq!(
+        output["photos"],
+        serde_json::json!([{ "url": "/media/albumX/a.jpg" }, { "url": "/media/albumX/b.jpg" }])
+    );
+    assert_eq!(output["pageInfo"]["hasNextPage"], true);
+    assert_eq!(output["folderImageCount"], 3);
+
+    let end_cursor = output["pageInfo"]["endCursor"].as_str().unwrap();
+    let data = schema
+        .execute(query(&format!(", after: \"{end_cursor}\"")))
+        .await
+        .into_result()
+        .unwrap()
+        .data
+        .into_json()?;
+    let output = &data["photosToReview"]["output"];
+    assert_eq!(
+        output["photos"],
+        serde_json::json!([{ "url": "/media/albumX/c.jpg" }])
+    );
+    assert_eq!(output["pageInfo"]["hasNextPage"], false);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_folders_to_review_and_folder_selection() -> Result<()> {
+    let media_dir = init_env()?;
+    write_image(&media_dir, "albumX", "x.jpg", "x")?;
+    write_image(&media_dir, "trips/albumY", "y1.jpg", "y1")?;
+    write_image(&media_dir, "trips/albumY", "y2.jpg", "y2")?;
+    write_reviewed_image(&media_dir, review_score("best"), "albumZ", "z.jpg", "z")?;
+    // only the buckets at the top of the media root are not reviewed
+    write_image(&media_dir, "trips/002-good", "g.jpg", "g")?;
+    let schema = photomanagerlib::model::new_schema(Some(&media_dir));
+
+    let data = schema
+        .execute("{ foldersToReview { success output { folders { path name imageCount } } } }")
+        .await
+        .into_result()
+        .unwrap()
+        .data;
+    assert_eq!(
+        data,
+        value!({
+            "foldersToReview": {
+                "success": true,
+                "output": {
+                    "folders": [
+                        { "path": "albumX", "name": "albumX", "imageCount": 1 },
+                        { "path": "trips/002-good", "name": "002-good", "imageCount": 1 },
+                        { "path": "trips/albumY", "name": "albumY", "imageCount": 2 }
+                    ]
+                }
+            }
+        })
+    );
+
+    let data = schema
+        .execute(
+            "{ photosToReview(folder: \"trips/albumY\") { output { photos { url } folderName } } }",
+        )
+        .await
+        .into_result()
+        .unwrap()
+        .data;
+    assert_eq!(
+        data,
+        value!({
+            "photosToReview": {
+                "output": {
+                    "photos": [ { "url": "/media/trips/albumY/y1.jpg" }, { "url": "/media/trips/albumY/y2.jpg" } ],
+                    "folderName": "albumY"
+                }
+            }
+        })
+    );
+
+    for folder in ["001-best/albumZ", "../albumX"] {
+        let data = schema
+            .execute(format!(
+                "{{ photosToReview(folder: \"{folder}\") {{ success }} }}"
+            ))
+            .await
+            .into_result()
+            .unwrap()
+            .data;
+        assert_eq!(data, value!({ "photosToReview": { "success": false } }));
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_get_photos_ordered() -> Result<()> {
+    let media_dir = init_env()?;
+    for (file_name, capture_time) in [
+        ("a.jpg", "2024:05:01 12:00:00"),
+        ("b.jpg", "2024:05:01 10:00:00"),
+        ("c.jpg", "2024:05:01 11:00:00"),
+    ] {
+        write_jpeg_with_capture_time(&media_dir, "albumX", file_name, capture_time)?;
+    }
+    let schema = photomanagerlib::model::new_schema(Some(&media_dir));
+    let query = |arguments: &str| {
+        format!(
+            "{{ photosToReview({arguments}) {{ output {{ photos {{ url }} pageInfo {{ endCursor }} }} }} }}"
+        )
+    };
+    let urls = |data: &serde_json::Value| {
+        data["photosToReview"]["output"]["photos"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|p| p["url"].as_str().unwrap().replace("/media/albumX/", ""))
+            .collect::<Vec<_>>()
+    };
+
+    let data = schema
+        .execute(query("orderBy: { field: CAPTURE_TIME }"))
+        .await
+        .into_result()
+        .unwrap()
+        .data
+        .into_json()?;
+    assert_eq!(urls(&data), ["b.jpg", "c.jpg", "a.jpg"]);
+
+    let data = schema
+        .execute(query("orderBy: { field: FILE_NAME, direction: DESC }"))
+        .await
+        .into_result()
+        .unwrap()
+        .data
+        .into_json()?;
+    assert_eq!(urls(&data), ["c.jpg", "b.jpg", "a.jpg"]);
+
+    let data = schema
+        .execute(query("orderBy: { field: CAPTURE_TIME }, first: 1"))
+        .await
+        .into_result()
+        .unwrap()
+        .data
+        .into_json()?;
+    let end_cursor = data["photosToReview"]["output"]["pageInfo"]["endCursor"]
+        .as_str()
+        .unwrap();
+    let data = schema
+        .execute(query(&format!(
+            "orderBy: {{ field: CAPTURE_TIME }}, first: 1, after: \"{end_cursor}\""
+        )))
+        .await
+        .into_result()
+        .unwrap()
+        .data
+        .into_json()?;
+    assert_eq!(urls(&data), ["c.jpg"]);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_get_photos_ordered_by_capture_time_with_offset() -> Result<()> {
+    let media_dir = init_env()?;
+    // shot on one trip by a phone in local time and a camera set to UTC
+    for (file_name, capture_time, offset) in [
+        ("phone.jpg", "2024:05:01 12:30:00", "+02:00"),
+        ("camera-1.jpg", "2024:05:01 10:00:00", "+00:00"),
+        ("camera-2.jpg", "2024:05:01 11:00:00", "+00:00"),
+    ] {
+        write_jpeg_with_exif(
+            &media_dir,
+            "albumX",
+            file_name,
+            &[
+                exif::Field {
+                    tag: exif::Tag::DateTimeOriginal,
+                    ifd_num: exif::In::PRIMARY,
+                    value: exif::Value::Ascii(vec![capture_time.as_bytes().to_vec()]),
+                },
+                exif::Field {
+                    tag: exif::Tag::OffsetTimeOriginal,
+                    ifd_num: exif::In::PRIMARY,
+                    value: exif::Value::Ascii(vec![offset.as_bytes().to_vec()]),
+                },
+            ],
+        )?;
+    }
+    let data = photomanagerlib::model::new_schema(Some(&media_dir))
+        .execute(
+            "{ photosToReview(orderBy: { field: CAPTURE_TIME }) { output { photos { url } } } }",
+        )
+        .await
+        .into_result()
+        .unwrap()
+        .data;
+    assert_eq!(
+        data,
+        value!({
+            "photosToReview": {
+                "output": {
+                    "photos": [
+                        { "url": "/media/albumX/camera-1.jpg" },
+                        { "url": "/media/albumX/phone.jpg" },
+                        { "url": "/media/albumX/camera-2.jpg" }
+                    ]
+                }
+            }
+        })
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_get_photos_detects_media_formats() -> Result<()> {
+    let media_dir = init_env()?;
+    write_image(&media_dir, "albumX", "IMG_0001.JPG", "i")?;
+    write_image(&media_dir, "albumX", "notes.txt", "not a photo")?;
+    // photos are told by their extension first, a misnamed file is not reviewed
+    std::fs::write(
+        PathBuf::from(&media_dir).join("albumX/export.bin"),
+        [0xFF, 0xD8, 0xFF, 0xE0],
+    )?;
+    let mut heic = vec![0, 0, 0, 24];
+    heic.extend(b"ftypheic");
+    heic.extend([0, 0, 0, 0]);
+    heic.extend(b"mif1heic");
+    std::fs::write(PathBuf::from(&media_dir).join("albumX/IMG_0002.heic"), heic)?;
+
+    let data = photomanagerlib::model::new_schema(Some(&media_dir))
+        .execute("{ photosToReview { output { photos { url } } } }")
+        .await
+        .into_result()
+        .unwrap()
+        .data;
+    assert_eq!(
+        data,
+        value!({
+            "photosToReview": {
+                "output": {
+                    "photos": [
+                        { "url": "/media/albumX/IMG_0001.JPG" },
+                        { "url": "/media/albumX/IMG_0002.heic" }
+                    ]
+                }
+            }
+        })
+    );
+    let data = photomanagerlib::model::new_schema(Some(&media_dir))
+        .execute("{ foldersToReview { output { folders { path imageCount } } } }")
+        .await
+        .into_result()
+        .unwrap()
+        .data;
+    assert_eq!(
+        data,
+        value!({
+            "foldersToReview": {
+                "output": { "folders": [{ "path": "albumX", "imageCount": 2 }] }
+            }
+        })
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_raw_and_jpeg_pair_reviewed_together() -> Result<()> {
+    let media_dir = init_env()?;
+    let jpeg = write_image(&media_dir, "albumX", "DSC_1234.JPG", "jpeg")?;
+    let raw = write_image(&media_dir, "albumX", "DSC_1234.NEF", "raw")?;
+    let schema = photomanagerlib::model::new_schema(Some(&media_dir));
+
+    let data = schema
+        .execute("{ photosToReview { output { photos { url rawUrl } folderImageCount } } }")
+        .await
+        .into_result()
+        .unwrap()
+        .data;
+    assert_eq!(
+        data,
+        value!({
+            "photosToReview": {
+                "output": {
+                    "photos": [
+                        { "url": "/media/albumX/DSC_1234.JPG", "rawUrl": "/media/albumX/DSC_1234.NEF" }
+                    ],
+                    "folderImageCount": 1
+                }
+            }
+        })
+    );
+
+    let data = schema
+        .execute(
+            "mutation { reviewPhoto(path: \"/media/albumX/DSC_1234.JPG\", score: BEST) { success } }",
+        )
+        .await
+        .into_result()
+        .unwrap()
+        .data;
+    assert_eq!(data, value!({ "reviewPhoto": { "success": true } }));
+    let bucket = PathBuf::from(&media_dir).join(review_score("best").as_str());
+    assert!(bucket.join("albumX/DSC_1234.JPG").exists());
+    assert!(bucket.join("albumX/DSC_1234.NEF").exists());
+    assert!(!raw.exists());
+
+    let data = schema
+        .execute("mutation { undo(path: \"/media/albumX/DSC_1234.JPG\", score: BEST) { success } }")
+        .await
+        .into_result()
+        .unwrap()
+        .data;
+    assert_eq!(data, value!({ "undo": { "success": true } }));
+    assert!(jpeg.exists());
+    assert!(raw.exists());
+    assert!(!bucket.join("albumX/DSC_1234.NEF").exists());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_sidecars_move_with_renamed_photo() -> Result<()> {
+    let media_dir = init_env()?;
+    write_reviewed_image(
+        &media_dir,
+        review_score("good"),
+        "albumX",
+        "IMG_0001.JPG",
+        "other contents",
+    )?;
+    let photo = write_image(&media_dir, "albumX", "IMG_0001.JPG", "i")?;
+    let sidecars = ["IMG_0001.JPG.xmp", "IMG_0001.AAE", "IMG_0001.JPG.json"]
+        .map(|name| photo.with_file_name(name));
+    for sidecar in &sidecars {
+        write_file(sidecar, "sidecar")?;
+    }
+    let schema = photomanagerlib::model::new_schema(Some(&media_dir));
+
+    schema
+        .execute(
+            "mutation { reviewPhoto(path: \"/media/albumX/IMG_0001.JPG\", score: GOOD) { success } }",
+        )
+        .await
+        .into_result()
+        .unwrap();
+    let bucket = PathBuf::from(&media_dir)
+        .join(review_score("good").as_str())
+        .join("albumX");
+    for name in [
+        "IMG_0001-1.JPG",
+        "IMG_0001-1.JPG.xmp",
+        "IMG_0001-1.AAE",
+        "IMG_0001-1.JPG.json",
+    ] {
+        assert!(bucket.join(name).exists(), "{name} should have been moved");
+    }
+    assert!(sidecars.iter().all(|sidecar| !sidecar.exists()));
+
+    let data = schema
+        .execute("mutation { undoLast { success } }")
+        .await
+        .into_result()
+        .unwrap()
+        .data;
+    assert_eq!(data, value!({ "undoLast": { "success": true } }));
+    assert!(photo.exists());
+    assert!(sidecars.iter().all(|sidecar| sidecar.exists()));
+    assert!(!bucket.join("IMG_0001-1.AAE").exists());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_sidecar_conflict_renames_photo_and_sidecars() -> Result<()> {
+    let media_dir = init_env()?;
+    // the photo is in the bucket already, but with a different sidecar
+    write_reviewed_image(
+        &media_dir,
+        review_score("good"),
+        "albumX",
+        "IMG_0001.JPG",
+        "i",
+    )?;
+    write_reviewed_image(
+        &media_dir,
+        review_score("good"),
+        "albumX",
+        "IMG_0001.JPG.xmp",
+        "other sidecar",
+    )?;
+    let photo = write_image(&media_dir, "albumX", "IMG_0001.JPG", "i")?;
+    write_file(&photo.with_file_name("IMG_0001.JPG.xmp"), "sidecar")?;
+    // IMG_0001.AAE might belong to IMG_0001.HEIC as well, so it stays
+    write_image(&media_dir, "albumX", "IMG_0001.HEIC", "heic")?;
+    let ambiguous = write_image(&media_dir, "albumX", "IMG_0001.AAE", "edits")?;
+
+    let data = photomanagerlib::model::new_schema(Some(&media_dir))
+        .execute(
+            "mutation { reviewPhoto(path: \"/media/albumX/IMG_0001.JPG\", score: GOOD) { success } }",
+        )
+        .await
+        .into_result()
+        .unwrap()
+        .data;
+    assert_eq!(data, value!({ "reviewPhoto": { "success": true } }));
+    let bucket = PathBuf::from(&media_dir)
+        .join(review_score("good").as_str())
+        .join("albumX");
+    assert!(!photo.exists());
+    assert_eq!(
+        std::fs::read_to_string(bucket.join("IMG_0001-1.JPG.xmp"))?,
+        "sidecar"
+    );
+    assert!(bucket.join("IMG_0001-1.JPG").exists());
+    assert_eq!(
+        std::fs::read_to_string(bucket.join("IMG_0001.JPG.xmp"))?,
+        "other sidecar"
+    );
+    assert!(ambiguous.exists());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_live_photo_pairs() -> Result<()> {
+    let media_dir = init_env()?;
+    write_image(&media_dir, "albumX", "IMG_0001.HEIC", "heic")?;
+    write_image(&media_dir, "albumX", "IMG_0001.MOV", "mov")?;
+    // PhotoSync can name the clip differently, the pair is found by the content identifier
+    let photo = write_jpeg_with_exif(
+        &media_dir,
+        "albumX",
+        "IMG_0002.JPG",
+        &[exif::Field {
+            tag: exif::Tag::MakerNote,
+            ifd_num: exif::In::PRIMARY,
+            value: exif::Value::Undefined(apple_maker_note("ABC-123"), 0),
+        }],
+    )?;
+    let clip = photo.with_file_name("IMG_0002_motion.MOV");
+    std::fs::write(&clip, quicktime_with_content_identifier("ABC-123"))?;
+    std::fs::write(
+        photo.with_file_name("IMG_0003_motion.MOV"),
+        quicktime_with_content_identifier("other"),
+    )?;
+    // a clip with a box size that runs past any file size is skipped
+    std::fs::write(
+        photo.with_file_name("IMG_0004_broken.MOV"),
+        [
+            &[0, 0, 0, 8][..],
+            b"free",
+            &[0, 0, 0, 1],
+            b"mdat",
+            &u64::MAX.to_be_bytes(),
+        ]
+        .concat(),
+    )?;
+    let schema = photomanagerlib::model::new_schema(Some(&media_dir));
+
+    let data = schema
+        .execute("{ photosToReview { output { photos { url motionUrl } } } }")
+        .await
+        .into_result()
+        .unwrap()
+        .data;
+    assert_eq!(
+        data,
+        value!({
+            "photosToReview": {
+                "output": {
+                    "photos": [
+                        { "url": "/media/albumX/IMG_0001.HEIC", "motionUrl": "/media/albumX/IMG_0001.MOV" },
+                        { "url": "/media/albumX/IMG_0002.JPG", "motionUrl": "/media/albumX/IMG_0002_motion.MOV" }
+                    ]
+                }
+            }
+        })
+    );
+
+    schema
+        .execute(
+            "mutation { reviewPhoto(path: \"/media/albumX/IMG_0002.JPG\", score: BEST) { success } }",
+        )
+        .await
+        .into_result()
+        .unwrap();
+    let bucket = PathBuf::from(&media_dir)
+        .join(review_score("best").as_str())
+        .join("albumX");
+    assert!(bucket.join("IMG_0002.JPG").exists());
+    assert!(bucket.join("IMG_0002_motion.MOV").exists());
+    assert!(!clip.exists());
+
+    schema
+        .execute("mutation { undoLast { success } }")
+        .await
+        .into_result()
+        .unwrap();
+    assert!(photo.exists() && clip.exists());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_get_photos_exif_metadata() -> Result<()> {
+    let media_dir = init_env()?;
+    let field = |tag, value| exif::Field {
+        tag,
+        ifd_num: exif::In::PRIMARY,
+        value,
+    };
+    let ascii = |s: &str| exif::Value::Ascii(vec![s.as_bytes().to_vec()]);
+    let rational = |values: &[(u32, u32)]| {
+        exif::Value::Rational(
+            values
+                .iter()
+                .map(|&(num, denom)| exif::Rational { num, denom })
+                .collect(),
+        )
+    };
+    write_jpeg_with_exif(
+        &media_dir,
+        "albumX",
+        "photo.jpg",
+        &[
+            field(exif::Tag::DateTimeOriginal, ascii("2024:05:01 12:30:00")),
+            field(exif::Tag::OffsetTimeOriginal, ascii("+02:00")),
+            field(exif::Tag::Make, ascii("FUJIFILM")),
+            field(exif::Tag::Model, ascii("X-T5")),
+            field(exif::Tag::LensModel, ascii("XF23mmF2 R WR")),
+            field(exif::Tag::FocalLength, rational(&[(23, 1)])),
+            field(exif::Tag::FNumber, rational(&[(28, 10)])),
+            field(exif::Tag::ExposureTime, rational(&[(1, 250)])),
+            field(
+                exif::Tag::PhotographicSensitivity,
+                exif::Value::Short(vec![400]),
+            ),
+            field(exif::Tag::GPSLatitudeRef, ascii("N")),
+            field(
+                exif::Tag::GPSLatitude,
+                rational(&[(52, 1), (30, 1), (0, 1)]),
+            ),
+            field(exif::Tag::GPSLongitudeRef, ascii("W")),
+            field(
+                exif::Tag::GPSLongitude,
+                rational(&[(4, 1), (15, 1), (0, 1)]),
+            ),
+            field(exif::Tag::Orientation, exif::Value::Short(vec![6])),
+        ],
+    )?;
+
+    let data = photomanagerlib::model::new_schema(Some(&media_dir))
+        .execute(
+            "{ photosToReview { output { photos { exif { captureTime captureTimeOffset make model \
+             lens focalLength aperture exposureTime iso latitude longitude width height \
+             orientation } } } } }",
+        )
+        .await
+        .into_result()
+        .unwrap()
+        .data;
+    assert_eq!(
+        data,
+        value!({
+            "photosToReview": {
+                "output": {
+                    "photos": [{
+                        "exif": {
+                            "captureTime": "2024-05-01T12:30:00",
+                            "captureTimeOffset": "+02:00",
+                            "make": "FUJIFILM",
+                            "model": "X-T5",
+                            "lens": "XF23mmF2 R WR",
+                            "focalLength": 23.0,
+                            "aperture": 2.8,
+                            "exposureTime": "1/250",
+                            "iso": 400,
+                            "latitude": 52.5,
+                            "longitude": -4.25,
+                            "width": 8,
+                            "height": 8,
+                            "orientation": 6
+                        }
+                    }]
+                }
+            }
+        })
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_review_photos_batch_rolls_back() -> Result<()> {
+    let media_dir = init_env()?;
+    let a = write_image(&media_dir, "albumX", "a.jpg", "a")?;
+    let b = write_image(&media_dir, "albumX", "b.jpg", "b")?;
+    write_file(&b.with_file_name("b.jpg.xmp"), "sidecar")?;
+    // b cannot be moved to the worst bucket, where a file is in the way of the album folder
+    write_file(
+        &PathBuf::from(&media_dir)
+            .join(review_score("worst").as_str())
+            .join("albumX"),
+        "not a folder",
+    )?;
+    // a copy of a is in the best bucket already, a replaces it
+    let best_a = write_image(
+        &PathBuf::from(&media_dir)
+            .join(review_score("best").as_str())
+            .to_string_lossy(),
+        "albumX",
+        "a.jpg",
+        "a",
+    )?;
+    let schema = photomanagerlib::model::new_schema(Some(&media_dir));
+    let mutation = |items: &str| {
+        format!(
+            "mutation {{ reviewPhotos(items: [{items}]) {{ success output {{ items {{ path success }} }} }} }}"
+        )
+    };
+
+    let data = schema
+        .execute(mutation(
+            "{ path: \"/media/albumX/a.jpg\", score: BEST }, \
+             { path: \"/media/albumX/b.jpg\", score: WORST }",
+        ))
+        .await
+        .into_result()
+        .unwrap()
+        .data;
+    assert_eq!(
+        data,
+        value!({
+            "reviewPhotos": {
+                "success": false,
+                "output": {
+                    "items": [
+                        { "path": "/media/albumX/a.jpg", "success": false },
+                        { "path": "/media/albumX/b.jpg", "success": false }
+                    ]
+                }
+            }
+        })
+    );
+    assert!(a.exists() && b.exists(), "a should have been moved back");
+    assert!(
+        best_a.exists(),
+        "the copy that was in the bucket before stays there"
+    );
+
+    let data = schema
+        .execute(mutation(
+            "{ path: \"/media/albumX/a.jpg\", score: BEST }, \
+             { path: \"/media/albumX/missing.jpg\", score: BEST }",
+        ))
+        .await
+        .into_result()
+        .unwrap()
+        .data;
+    assert_eq!(data.into_json()?["reviewPhotos"]["success"], false);
+    assert!(
+        a.exists(),
+        "nothing is moved when one of the photos is invalid"
+    );
+
+    let data = schema
+        .execute(mutation(
+            "{ path: \"/media/albumX/a.jpg\", score: BEST }, \
+             { path: \"/media/albumX/b.jpg\", score: GOOD }",
+        ))
+        .await
+        .into_result()
+        .unwrap()
+        .data;
+    assert_eq!(data.into_json()?["reviewPhotos"]["success"], true);
+    assert!(!a.exists() && !b.exists());
+    assert!(
+        PathBuf::from(&media_dir)
+            .join(review_score("good").as_str())
+            .join("albumX/b.jpg.xmp")
+            .exists()
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_finish_folder() -> Result<()> {
+    let media_dir = init_env()?;
+    write_image(&media_dir, "trips/rome", "1.jpg", "1")?;
+    write_image(&media_dir, "trips/rome", "2.jpg", "2")?;
+    write_image(&media_dir, "trips/rome", "2.NEF", "raw")?;
+    write_image(&media_dir, "trips/paris", "3.jpg", "3")?;
+    write_reviewed_image(&media_dir, review_score("good"), "rome", "1.jpg", "other")?;
+    // deferred photos and copies of reviewed photos are not reviewed, like in photosToReview
+    let deferred = write_image(&media_dir, "trips/rome", "4.jpg", "4")?;
+    write_image(&media_dir, "trips/rome", "copy.jpg", "reviewed")?;
+    write_reviewed_image(
+        &media_dir,
+        review_score("best"),
+        "naples",
+        "5.jpg",
+        "reviewed",
+    )?;
+
+    let schema = photomanagerlib::model::new_schema(Some(&media_dir));
+    let data = schema
+        .execute("mutation { deferPhoto(path: \"/media/trips/rome/4.jpg\") { success } }")
+        .await
+        .into_result()
+        .unwrap()
+        .data;
+    assert_eq!(data, value!({ "deferPhoto": { "success": true } }));
+    let data = schema
+        .execute(
+            "mutation { finishFolder(folder: \"trips/rome\", score: GOOD) { success output { \
+             reviewedCount failedCount items { path success output } } } }",
+        )
+        .await
+        .into_result()
+        .unwrap()
+        .data;
+    assert_eq!(
+        data,
+        value!({
+            "finishFolder": {
+                "success": true,
+                "output": {
+                    "reviewedCount": 2,
+                    "failedCount": 0,
+                    "items": [
+                        { "path": "/media/trips/rome/1.jpg", "success": true, "output": "/media/002-good/rome/1-1.jpg" },
+                        { "path": "/media/trips/rome/2.jpg", "success": true, "output": "/media/002-good/rome/2.jpg" }
+                    ]
+                }
+            }
+        })
+    );
+    assert!(
+        PathBuf::from(&media_dir)
+            .join("002-good/rome/2.NEF")
+            .exists()
+    );
+    assert_eq!(
+        std::fs::read_dir(PathBuf::from(&media_dir).join("trips/rome"))?.count(),
+        1
+    );
+    assert!(deferred.exists());
+    assert!(
+        PathBuf::from(&media_dir)
+            .join(photomanagerlib::reviewscore::ReviewScore::already_reviewed().as_str())
+            .join("rome")
+            .join("copy.jpg")
+            .exists()
+    );
+    assert!(PathBuf::from(&media_dir).join("trips/paris/3.jpg").exists());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_defer_photo() -> Result<()> {
+    let media_dir = init_env()?;
+    write_image(&media_dir, "albumX", "1.jpg", "1")?;
+    write_image(&media_dir, "albumX", "2.jpg", "2")?;
+    let schema = photomanagerlib::model::new_schema(Some(&media_dir));
+
+    let data = schema
+        .execute("mutation { deferPhoto(path: \"/media/albumX/1.jpg\") { success output } }")
+        .await
+        .into_result()
+        .unwrap()
+        .data;
+    assert_eq!(
+        data,
+        value!({ "deferPhoto": { "success": true, "output": "/media/albumX/1.jpg" } })
+    );
+    assert!(
+        PathBuf::from(&media_dir).join("albumX/1.jpg").exists(),
+        "a deferred photo is not moved"
+    );
+
+    let data = schema
+        .execute(
+            "{ photosToReview { output { photos { url } folderImageCount } } \
+             deferredPhotos(folder: \"albumX\") { output { photos { url album } } } }",
+        )
+        .await
+        .into_result()
+        .unwrap()
+        .data;
+    assert_eq!(
+        data,
+        value!({
+            "photosToReview": {
+                "output": {
+                    "photos": [{ "url": "/media/albumX/2.jpg" }],
+                    "folderImageCount": 1
+                }
+            },
+            "deferredPhotos": {
+                "output": {
+                    "photos": [{ "url": "/media/albumX/1.jpg", "album": "albumX" }]
+                }
+            }
+        })
+    );
+
+    // the deferred state survives a restart, undeferring returns the photo to the queue
+    let schema = photomanagerlib::model::new_schema(Some(&media_dir));
+    let data = schema
+        .execute("mutation { undeferPhoto(path: \"/media/albumX/1.jpg\") { success } } ")
+        .await
+        .into_result()
+        .unwrap()
+        .data;
+    assert_eq!(data, value!({ "undeferPhoto": { "success": true } }));
+    let data = schema
+        .execute(
+            "{ photosToReview { output { photos { url } } } \
+             deferredPhotos { output { photos { url } } } }",
+        )
+        .await
+        .into_result()
+        .unwrap()
+        .data;
+    assert_eq!(
+        data,
+        value!({
+            "photosToReview": {
+                "output": {
+                    "photos": [{ "url": "/media/albumX/1.jpg" }, { "url": "/media/albumX/2.jpg" }]
+                }
+            },
+            "deferredPhotos": { "output": { "photos": [] } }
+        })
+    );
+    Ok(())
+}
+
+// an Apple iOS maker note with the ContentIdentifier tag
+fn apple_maker_note(content_identifier: &str) -> Vec<u8> {
+    let value = [content_identifier.as_bytes(), b"\0"].concat();
+    let mut note = b"Apple iOS\0\0\x01MM".to_vec();
+    note.extend(1u16.to_be_bytes());
+    note.extend(0x11u16.to_be_bytes());
+    note.extend(2u16.to_be_bytes());
+    note.extend(u32::try_from(value.len()).unwrap().to_be_bytes());
+    // the value follows the entry and the next IFD offset
+    note.extend(32u32.to_be_bytes());
+    note.extend(0u32.to_be_bytes());
+    note.extend(value);
+    note
+}
+
+// a QuickTime file with only the metadata of a Live Photo clip
+fn quicktime_with_content_identifier(content_identifier: &str) -> Vec<u8> {
+    fn mp4_box(box_type: &[u8], contents: &[u8]) -> Vec<u8> {
+        let size = u32::try_from(8 + contents.len()).unwrap();
+        [&size.to_be_bytes(), box_type, contents].concat()
+    }
+    let key = b"com.apple.quicktime.content.identifier";
+    let keys = [
+        &[0u8; 4][..],
+        &1u32.to_be_bytes(),
+        &u32::try_from(8 + key.len()).unwrap().to_be_bytes(),
+        b"mdta",
+        key,
+    ]
+    .concat();
+    let data = [
+        &1u32.to_be_bytes(),
+        &[0u8; 4],
+        content_identifier.as_bytes(),
+    ]
+    .concat();
+    let ilst = mp4_box(&1u32.to_be_bytes(), &mp4_box(b"data", &data));
+    let meta = [
+        mp4_box(b"hdlr", &[0u8; 24]),
+        mp4_box(b"keys", &keys),
+        mp4_box(b"ilst", &ilst),
+    ]
+    .concat();
+    [
+        mp4_box(b"ftyp", b"qt  \0\0\0\0qt  "),
+        mp4_box(b"moov", &mp4_box(b"meta", &meta)),
+    ]
+    .concat()
+}
+
+// writes a small jpeg with an EXIF DateTimeOriginal tag
+fn write_jpeg_with_capture_time(
+    folder: &str,
+    album: &str,
+    file_name: &str,
+    capture_time: &str,
+) -> Result<PathBuf> {
+    write_jpeg_with_exif(
+        folder,
+        album,
+        file_name,
+        &[exif::Field {
+            tag: exif::Tag::DateTimeOriginal,
+            ifd_num: exif::In::PRIMARY,
+            value: exif::Value::Ascii(vec![capture_time.as_bytes().to_vec()]),
+        }],
+    )
+}
+
+fn write_jpeg_with_exif(
+    folder: &str,
+    album: &str,
+    file_name: &str,
+    fields: &[exif::Field],
+) -> Result<PathBuf> {
+    let mut jpeg = std::io::Cursor::new(vec![]);
+    image::RgbImage::from_pixel(8, 8, image::Rgb([200, 100, 50]))
+        .write_to(&mut jpeg, image::ImageFormat::Jpeg)?;
+    let jpeg = jpeg.into_inner();
+
+    let mut writer = exif::experimental::Writer::new();
+    for field in fields {
+        writer.push_field(field);
+    }
+    let mut tiff = std::io::Cursor::new(vec![]);
+    writer.write(&mut tiff, false)?;
+    let tiff = tiff.into_inner();
+
+    let segment_length = u16::try_from(2 + 6 + tiff.len())?;
+    let mut contents = jpeg[..2].to_vec();
+    contents.extend([0xFF, 0xE1]);
+    contents.extend(segment_length.to_be_bytes());
+    contents.extend(b"Exif\0\0");
+    contents.extend(tiff);
+    contents.extend(&jpeg[2..]);
+
+    let path = PathBuf::from(folder).join(album).join(file_name);
+    std::fs::create_dir_all(path.parent().unwrap())?;
+    std::fs::write(&path, contents)?;
+    Ok(path)
+}
+
+fn review_score(name: &str) -> photomanagerlib::reviewscore::ReviewScore {
+    photomanagerlib::reviewscore::ReviewScore::from_name(name).unwrap()
+}
+
 fn init_env() -> Result<String> {
     let tempdir = std::env::temp_dir().join("photomanager-tests");
 
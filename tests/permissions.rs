@@ -0,0 +1,184 @@
+mod common;
+
+use anyhow::Result;
+use async_graphql::value;
+use common::{execute, temp_media_root};
+use photomanagerlib::auth::Authenticator;
+use std::path::PathBuf;
+
+const CONFIG: &str = r#"
+[[users]]
+name = "mum"
+token = "mum-0123456789abcdef"
+role = "admin"
+
+[[users]]
+name = "dad"
+token = "dad-0123456789abcdef"
+role = "reviewer"
+
+[[users]]
+name = "kid"
+token = "kid-0123456789abcdef"
+role = "reviewer"
+folders = ["phones/kid"]
+
+[[users]]
+name = "grandma"
+token = "grandma-0123456789abcdef"
+"#;
+
+#[tokio::test]
+async fn test_roles_and_folder_access() -> Result<()> {
+    let media_dir = temp_media_root(
+        "permissions",
+        &["phones/dad/1.jpg", "phones/kid/2.jpg", "phones/kid/3.jpg"],
+    )?;
+    unsafe { std::env::set_var("PUBLIC_URL", "http://integration-test") };
+    let authenticator = Authenticator::from_config(CONFIG)?;
+    let user = |name: &str| {
+        authenticator
+            .authenticate(Some(&format!("{name}-0123456789abcdef")))
+            .unwrap()
+    };
+    let schema = photomanagerlib::model::new_schema(Some(&media_dir));
+
+    assert_eq!(
+        execute(
+            &schema,
+            "{ foldersToReview { output { folders { path } } } }",
+            user("kid")
+        )
+        .await,
+        value!({ "foldersToReview": { "output": { "folders": [{ "path": "phones/kid" }] } } })
+    );
+    assert_eq!(
+        execute(
+            &schema,
+            "{ photosToReview(folder: \"phones/dad\") { success } }",
+            user("kid")
+        )
+        .await,
+        value!({ "photosToReview": { "success": false } })
+    );
+
+    let review = |path: &str| {
+        format!(
+            "mutation {{ reviewPhoto(path: \"/media/phones/{path}\", score: GOOD) {{ success }} }}"
+        )
+    };
+    assert_eq!(
+        execute(&schema, &review("kid/2.jpg"), user("grandma")).await,
+        value!({ "reviewPhoto": { "success": false } }),
+        "viewers cannot review"
+    );
+    assert_eq!(
+        execute(&schema, &review("dad/1.jpg"), user("kid")).await,
+        value!({ "reviewPhoto": { "success": false } }),
+        "the kid can only review the photos of their own phone"
+    );
+    assert!(PathBuf::from(&media_dir).join("phones/dad/1.jpg").exists());
+    for path in ["kid/2.jpg", "kid/3.jpg"] {
+        assert_eq!(
+            execute(&schema, &review(path), user("kid")).await,
+            value!({ "reviewPhoto": { "success": true } })
+        );
+    }
+
+    // reviewers only undo their own reviews, admins undo the reviews of everyone
+    let undo = "mutation { undo(path: \"/media/phones/kid/2.jpg\", score: GOOD) { success } }";
+    assert_eq!(
+        execute(&schema, undo, user("dad")).await,
+        value!({ "undo": { "success": false } })
+    );
+    assert_eq!(
+        execute(&schema, "mutation { undoLast { success } }", user("dad")).await,
+        value!({ "undoLast": { "success": false } })
+    );
+    assert_eq!(
+        execute(&schema, undo, user("mum")).await,
+        value!({ "undo": { "success": true } })
+    );
+    assert_eq!(
+        execute(
+            &schema,
+            "mutation { undoLast { success output } }",
+            user("kid")
+        )
+        .await,
+        value!({ "undoLast": { "success": true, "output": "/media/phones/kid/3.jpg" } })
+    );
+    assert!(PathBuf::from(&media_dir).join("phones/kid/2.jpg").exists());
+    assert!(PathBuf::from(&media_dir).join("phones/kid/3.jpg").exists());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_paths_outside_the_media_root_are_rejected() -> Result<()> {
+    let media_dir = temp_media_root("permissions", &["phones/dad/1.jpg"])?;
+    let outside_dir = format!("{media_dir}-outside");
+    let dad_photo = PathBuf::from(&media_dir).join("phones/dad/1.jpg");
+    let outside_photo = PathBuf::from(&outside_dir).join("photo.jpg");
+    std::fs::create_dir_all(&outside_dir)?;
+    std::fs::write(&outside_photo, "i")?;
+    unsafe { std::env::set_var("PUBLIC_URL", "http://integration-test") };
+    let authenticator = Authenticator::from_config(CONFIG)?;
+    let user = |name: &str| {
+        authenticator
+            .authenticate(Some(&format!("{name}-0123456789abcdef")))
+            .unwrap()
+    };
+    let schema = photomanagerlib::model::new_schema(Some(&media_dir));
+
+    let outside_name = PathBuf::from(&outside_dir)
+        .file_name()
+        .unwrap()
+        .to_string_lossy()
+        .into_owned();
+    for path in [
+        format!("/media/../{outside_name}/photo.jpg"),
+        format!("/media/phones/../../{outside_name}/photo.jpg"),
+        "/media-outside/photo.jpg".into(),
+    ] {
+        assert_eq!(
+            execute(
+                &schema,
+                &format!(
+                    "mutation {{ ratePhoto(path: \"{path}\", stars: 3) {{ success output }} }}"
+                ),
+                user("mum")
+            )
+            .await,
+            value!({ "ratePhoto": { "success": false, "output": format!("Invalid path '{path}'") } })
+        );
+        assert_eq!(
+            execute(
+                &schema,
+                &format!("mutation {{ reviewPhoto(path: \"{path}\", score: GOOD) {{ success }} }}"),
+                user("mum")
+            )
+            .await,
+            value!({ "reviewPhoto": { "success": false } })
+        );
+    }
+    assert!(outside_photo.exists());
+    assert!(!PathBuf::from(format!("{}.xmp", outside_photo.display())).exists());
+
+    // the access is checked before the sidecar is read, an unreadable sidecar does not matter
+    std::fs::create_dir(format!("{}.xmp", dad_photo.display()))?;
+    assert_eq!(
+        execute(
+            &schema,
+            "mutation { ratePhoto(path: \"/media/phones/dad/1.jpg\", stars: 3) { success output } }",
+            user("kid")
+        )
+        .await,
+        value!({
+            "ratePhoto": {
+                "success": false,
+                "output": "kid has no access to phones/dad/1.jpg"
+            }
+        })
+    );
+    Ok(())
+}
@@ -0,0 +1,208 @@
+use anyhow::Result;
+use async_graphql::value;
+use photomanagerlib::preview::{PreviewCache, PreviewSize};
+use std::path::PathBuf;
+
+#[tokio::test]
+async fn test_preview_urls_and_cache() -> Result<()> {
+    let media_dir = std::env::temp_dir()
+        .join(format!("photomanager-preview-{}", fastrand::u64(..)))
+        .to_str()
+        .unwrap()
+        .to_string();
+    let photo = PathBuf::from(&media_dir).join("albumX/photo.png");
+    std::fs::create_dir_all(photo.parent().unwrap())?;
+    image::RgbImage::from_fn(1000, 500, |x, _| image::Rgb([(x % 256) as u8, 0, 0])).save(&photo)?;
+    unsafe { std::env::set_var("PUBLIC_URL", "http://integration-test") };
+
+    let data = photomanagerlib::model::new_schema(Some(&media_dir))
+        .execute("{ photosToReview { output { photos { url thumbnailUrl previewUrl } } } }")
+        .await
+        .into_result()
+        .unwrap()
+        .data;
+    assert_eq!(
+        data,
+        value!({
+            "photosToReview": {
+                "output": {
+                    "photos": [{
+                        "url": "/media/albumX/photo.png",
+                        "thumbnailUrl": "/preview/thumbnail/albumX/photo.png",
+                        "previewUrl": "/preview/large/albumX/photo.png"
+                    }]
+                }
+            }
+        })
+    );
+
+    // a limit of one byte only keeps the most recent preview
+    let cache = PreviewCache::new(&media_dir, 1);
+    let thumbnail = cache.get("albumX/photo.png", PreviewSize::Thumbnail)?;
+    assert_eq!(image::image_dimensions(&thumbnail)?, (320, 160));
+    assert!(thumbnail.starts_with(PathBuf::from(&media_dir).join(".photomanager")));
+    assert_eq!(
+        cache.get("albumX/photo.png", PreviewSize::Thumbnail)?,
+        thumbnail
+    );
+
+    let large = cache.get("albumX/photo.png", PreviewSize::Large)?;
+    assert_eq!(
+        image::image_dimensions(&large)?,
+        (1000, 500),
+        "photos are not upscaled"
+    );
+    assert!(
+        !thumbnail.exists(),
+        "the least recently used preview is evicted"
+    );
+
+    image::RgbImage::from_pixel(100, 100, image::Rgb([0, 0, 0])).save(&photo)?;
+    let changed = cache.get("albumX/photo.png", PreviewSize::Large)?;
+    assert_ne!(changed, large, "a changed photo gets a new preview");
+    assert_eq!(image::image_dimensions(&changed)?, (100, 100));
+
+    assert!(cache.get("../photo.png", PreviewSize::Large).is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_renditions_of_raw_and_tiff() -> Result<()> {
+    let media_dir = std::env::temp_dir()
+        .join(format!("photomanager-preview-{}", fastrand::u64(..)))
+        .to_str()
+        .unwrap()
+        .to_string();
+    let album = PathBuf::from(&media_dir).join("albumX");
+    std::fs::create_dir_all(&album)?;
+    image::RgbImage::from_pixel(40, 30, image::Rgb([0, 200, 0])).save(album.join("scan.tif"))?;
+    // a RAW file with a small and a large embedded JPEG preview between the sensor data
+    let mut raw = b"II*\0\x08\0\0\0".to_vec();
+    raw.extend(jpeg(16, 8)?);
+    raw.extend([0u8; 100]);
+    raw.extend(jpeg(64, 32)?);
+    raw.extend([0xFF, 0xD8, 0xFF, 0x00]);
+    std::fs::write(album.join("DSC_0001.NEF"), raw)?;
+    unsafe { std::env::set_var("PUBLIC_URL", "http://integration-test") };
+
+    let data = photomanagerlib::model::new_schema(Some(&media_dir))
+        .execute("{ photosToReview { output { photos { url } } } }")
+        .await
+        .into_result()
+        .unwrap()
+        .data;
+    assert_eq!(
+        data,
+        value!({
+            "photosToReview": {
+                "output": {
+                    "photos": [
+                        { "url": "/preview/full/albumX/DSC_0001.NEF" },
+                        { "url": "/preview/full/albumX/scan.tif" }
+                    ]
+                }
+            }
+        })
+    );
+
+    let cache = PreviewCache::new(&media_dir, u64::MAX);
+    let rendition = cache.get("albumX/DSC_0001.NEF", PreviewSize::Full)?;
+    assert_eq!(image::image_dimensions(&rendition)?, (64, 32));
+    let rendition = cache.get("albumX/scan.tif", PreviewSize::Full)?;
+    assert_eq!(
+        image::ImageReader::open(&rendition)?
+            .with_guessed_format()?
+            .format(),
+        Some(image::ImageFormat::Jpeg)
+    );
+    assert_eq!(image::image_dimensions(&rendition)?, (40, 30));
+    Ok(())
+}
+
+fn jpeg(width: u32, height: u32) -> Result<Vec<u8>> {
+    let mut jpeg = std::io::Cursor::new(vec![]);
+    image::RgbImage::from_pixel(width, height, image::Rgb([200, 100, 50]))
+        .write_to(&mut jpeg, image::ImageFormat::Jpeg)?;
+    Ok(jpeg.into_inner())
+}
+
+#[test]
+fn test_preview_applies_exif_orientation() -> Result<()> {
+    let media_dir = std::env::temp_dir()
+        .join(format!("photomanager-preview-{}", fastrand::u64(..)))
+        .to_str()
+        .unwrap()
+        .to_string();
+    let photo = PathBuf::from(&media_dir).join("albumX/portrait.jpg");
+    std::fs::create_dir_all(photo.parent().unwrap())?;
+    // stored in landscape, to be rotated 90 degrees clockwise for display
+    let mut writer = exif::experimental::Writer::new();
+    let orientation = exif::Field {
+        tag: exif::Tag::Orientation,
+        ifd_num: exif::In::PRIMARY,
+        value: exif::Value::Short(vec![6]),
+    };
+    writer.push_field(&orientation);
+    let mut tiff = std::io::Cursor::new(vec![]);
+    writer.write(&mut tiff, false)?;
+    let tiff = tiff.into_inner();
+    let jpeg = jpeg(40, 20)?;
+    let mut contents = jpeg[..2].to_vec();
+    contents.extend([0xFF, 0xE1]);
+    contents.extend(u16::try_from(2 + 6 + tiff.len())?.to_be_bytes());
+    contents.extend(b"Exif\0\0");
+    contents.extend(tiff);
+    contents.extend(&jpeg[2..]);
+    std::fs::write(&photo, contents)?;
+
+    let cache = PreviewCache::new(&media_dir, u64::MAX);
+    let preview = cache.get("albumX/portrait.jpg", PreviewSize::Large)?;
+    assert_eq!(image::image_dimensions(&preview)?, (20, 40));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_avif_is_reviewed_and_served_as_original() -> Result<()> {
+    let media_dir = std::env::temp_dir()
+        .join(format!("photomanager-preview-{}", fastrand::u64(..)))
+        .to_str()
+        .unwrap()
+        .to_string();
+    let photo = PathBuf::from(&media_dir).join("albumX/photo.avif");
+    std::fs::create_dir_all(photo.parent().unwrap())?;
+    let mut avif = vec![0, 0, 0, 24];
+    avif.extend(b"ftypavif");
+    avif.extend([0, 0, 0, 0]);
+    avif.extend(b"mif1avif");
+    std::fs::write(&photo, avif)?;
+    unsafe { std::env::set_var("PUBLIC_URL", "http://integration-test") };
+
+    let data = photomanagerlib::model::new_schema(Some(&media_dir))
+        .execute("{ photosToReview { output { photos { url } } } }")
+        .await
+        .into_result()
+        .unwrap()
+        .data;
+    assert_eq!(
+        data,
+        value!({
+            "photosToReview": {
+                "output": { "photos": [{ "url": "/media/albumX/photo.avif" }] }
+            }
+        })
+    );
+
+    // browsers display AVIF, so the original is served when no preview can be rendered
+    let cache = PreviewCache::new(&media_dir, u64::MAX);
+    assert!(
+        cache
+            .get("albumX/photo.avif", PreviewSize::Thumbnail)
+            .is_err()
+    );
+    assert_eq!(
+        cache.original("albumX/photo.avif"),
+        Some((photo, "image/avif"))
+    );
+    assert_eq!(cache.original("albumX/missing.tif"), None);
+    Ok(())
+}
@@ -0,0 +1,126 @@
+use anyhow::Result;
+use async_graphql::futures_util::{Stream, StreamExt};
+use async_graphql::{Response, Value, value};
+use std::path::PathBuf;
+use std::time::Duration;
+
+#[tokio::test]
+async fn test_review_events() -> Result<()> {
+    let media_dir = init_media_dir()?;
+    write_photo(&media_dir, "albumX/1.jpg")?;
+    write_photo(&media_dir, "albumX/2.jpg")?;
+    let schema = photomanagerlib::model::new_schema(Some(&media_dir));
+    let mut reviewed = subscribe(
+        schema.execute_stream("subscription { photoReviewed { path destination score } }"),
+    )
+    .await;
+    let mut queue_counts =
+        subscribe(schema.execute_stream(
+            "subscription { queueCountChanged(folder: \"albumX\") { folder count } }",
+        ))
+        .await;
+
+    let data = schema
+        .execute("mutation { reviewPhoto(path: \"/media/albumX/1.jpg\", score: GOOD) { success } }")
+        .await
+        .into_result()
+        .unwrap()
+        .data;
+    assert_eq!(data, value!({ "reviewPhoto": { "success": true } }));
+    assert_eq!(
+        next_event(&mut reviewed).await,
+        value!({
+            "photoReviewed": {
+                "path": "/media/albumX/1.jpg",
+                "destination": "/media/002-good/albumX/1.jpg",
+                "score": "GOOD"
+            }
+        })
+    );
+    assert_eq!(
+        next_event(&mut queue_counts).await,
+        value!({ "queueCountChanged": { "folder": "albumX", "count": 1 } })
+    );
+
+    schema
+        .execute("mutation { deferPhoto(path: \"/media/albumX/2.jpg\") { success } }")
+        .await
+        .into_result()
+        .unwrap();
+    assert_eq!(
+        next_event(&mut queue_counts).await,
+        value!({ "queueCountChanged": { "folder": "albumX", "count": 0 } })
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_new_photos_arrived() -> Result<()> {
+    let media_dir = init_media_dir()?;
+    write_photo(&media_dir, "albumX/1.jpg")?;
+    // the server schema watches MEDIA_ROOT
+    unsafe {
+        std::env::set_var("MEDIA_ROOT", &media_dir);
+        std::env::set_var("WATCH_DEBOUNCE_MS", "200");
+    };
+    let schema = photomanagerlib::model::new_schema(None);
+    let mut arrived =
+        subscribe(schema.execute_stream(
+            "subscription { newPhotosArrived(folder: \"inbox\") { folder count } }",
+        ))
+        .await;
+    let mut queue_count =
+        subscribe(schema.execute_stream(
+            "subscription { queueCountChanged(folder: \"inbox\") { folder count } }",
+        ))
+        .await;
+
+    // photos in other folders are filtered out
+    write_photo(&media_dir, "albumX/2.jpg")?;
+    for i in 0..3 {
+        write_photo(&media_dir, &format!("inbox/{i}.jpg"))?;
+    }
+    assert_eq!(
+        next_event(&mut arrived).await,
+        value!({ "newPhotosArrived": { "folder": "inbox", "count": 3 } })
+    );
+    assert_eq!(
+        next_event(&mut queue_count).await,
+        value!({ "queueCountChanged": { "folder": "inbox", "count": 3 } })
+    );
+    Ok(())
+}
+
+// polls the subscription once, so that it is subscribed before events are published
+async fn subscribe<S: Stream<Item = Response> + Unpin>(mut stream: S) -> S {
+    let _ = tokio::time::timeout(Duration::from_millis(10), stream.next()).await;
+    stream
+}
+
+async fn next_event(stream: &mut (impl Stream<Item = Response> + Unpin)) -> Value {
+    tokio::time::timeout(Duration::from_secs(10), stream.next())
+        .await
+        .expect("no event within 10 seconds")
+        .expect("the subscription ended")
+        .into_result()
+        .unwrap()
+        .data
+}
+
+fn init_media_dir() -> Result<String> {
+    let media_dir = std::env::temp_dir()
+        .join(format!("photomanager-subscriptions-{}", fastrand::u64(..)))
+        .to_str()
+        .unwrap()
+        .to_string();
+    std::fs::create_dir_all(&media_dir)?;
+    unsafe { std::env::set_var("PUBLIC_URL", "http://integration-test") };
+    Ok(media_dir)
+}
+
+fn write_photo(media_dir: &str, relative_path: &str) -> Result<()> {
+    let path = PathBuf::from(media_dir).join(relative_path);
+    std::fs::create_dir_all(path.parent().unwrap())?;
+    std::fs::write(path, relative_path)?;
+    Ok(())
+}
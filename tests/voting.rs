@@ -0,0 +1,172 @@
+mod common;
+
+use anyhow::Result;
+use async_graphql::value;
+use common::{execute, temp_media_root, user};
+use photomanagerlib::auth::{Authenticator, Role};
+use photomanagerlib::reviewscore::ReviewScore;
+use photomanagerlib::voting::{VotingRule, init_voting_rule};
+use std::path::PathBuf;
+
+// the voting rule is loaded once per process, so tests with a rule live in their own test binary
+#[tokio::test]
+async fn test_vote_until_quorum() -> Result<()> {
+    let media_dir = temp_media_root("voting", &["albumX/1.jpg", "albumX/2.jpg"])?;
+    let config_path = PathBuf::from(&media_dir).join("voting.toml");
+    std::fs::write(&config_path, "rule = \"quorum\"\nvotes = 2\nvoters = 3\n")?;
+    unsafe {
+        std::env::set_var("VOTING_CONFIG", &config_path);
+        std::env::set_var("PUBLIC_URL", "http://integration-test");
+    };
+    let schema = photomanagerlib::model::new_schema(Some(&media_dir));
+    let vote = |score: &str| {
+        format!(
+            "mutation {{ votePhoto(path: \"/media/albumX/1.jpg\", score: {score}) {{ success output {{ votes {{ voter score }} resolvedScore destination }} }} }}"
+        )
+    };
+
+    // a changed vote replaces the earlier vote of the voter
+    execute(&schema, &vote("BEST"), user("dad", Role::Reviewer)).await;
+    let data = execute(&schema, &vote("GOOD"), user("dad", Role::Reviewer)).await;
+    assert_eq!(
+        data,
+        value!({
+            "votePhoto": {
+                "success": true,
+                "output": {
+                    "votes": [{ "voter": "dad", "score": "GOOD" }],
+                    "resolvedScore": null,
+                    "destination": null
+                }
+            }
+        })
+    );
+    assert!(PathBuf::from(&media_dir).join("albumX/1.jpg").exists());
+    let data = execute(
+        &schema,
+        "{ photosToReview { output { photos { url votes { voter score } } } } }",
+        user("kid", Role::Reviewer),
+    )
+    .await;
+    assert_eq!(
+        data,
+        value!({
+            "photosToReview": {
+                "output": {
+                    "photos": [
+                        {
+                            "url": "/media/albumX/1.jpg",
+                            "votes": [{ "voter": "dad", "score": "GOOD" }]
+                        },
+                        { "url": "/media/albumX/2.jpg", "votes": [] }
+                    ]
+                }
+            }
+        })
+    );
+
+    // the second agreeing vote reaches the quorum and reviews the photo
+    let data = execute(&schema, &vote("GOOD"), user("kid", Role::Reviewer)).await;
+    assert_eq!(
+        data,
+        value!({
+            "votePhoto": {
+                "success": true,
+                "output": {
+                    "votes": [
+                        { "voter": "dad", "score": "GOOD" },
+                        { "voter": "kid", "score": "GOOD" }
+                    ],
+                    "resolvedScore": "GOOD",
+                    "destination": "/media/002-good/albumX/1.jpg"
+                }
+            }
+        })
+    );
+    assert!(!PathBuf::from(&media_dir).join("albumX/1.jpg").exists());
+    assert!(
+        PathBuf::from(&media_dir)
+            .join("002-good/albumX/1.jpg")
+            .exists()
+    );
+
+    // a vote that comes in right after the photo has been resolved gets the resolved score
+    let data = execute(&schema, &vote("BEST"), user("mum", Role::Reviewer)).await;
+    assert_eq!(
+        data,
+        value!({
+            "votePhoto": {
+                "success": true,
+                "output": {
+                    "votes": [],
+                    "resolvedScore": "GOOD",
+                    "destination": "/media/002-good/albumX/1.jpg"
+                }
+            }
+        })
+    );
+
+    // viewers cannot vote, and only admins can review without voting
+    let review =
+        "mutation { reviewPhoto(path: \"/media/albumX/2.jpg\", score: WORST) { success } }";
+    let data = execute(
+        &schema,
+        "mutation { votePhoto(path: \"/media/albumX/2.jpg\", score: WORST) { success output { message } } }",
+        user("grandma", Role::Viewer),
+    )
+    .await;
+    assert_eq!(
+        data,
+        value!({
+            "votePhoto": {
+                "success": false,
+                "output": { "message": "grandma needs the Reviewer role for this" }
+            }
+        })
+    );
+    let data = execute(&schema, review, user("dad", Role::Reviewer)).await;
+    assert_eq!(data, value!({ "reviewPhoto": { "success": false } }));
+    let data = execute(&schema, review, user("mum", Role::Admin)).await;
+    assert_eq!(data, value!({ "reviewPhoto": { "success": true } }));
+    assert!(
+        PathBuf::from(&media_dir)
+            .join("003-worst/albumX/2.jpg")
+            .exists()
+    );
+
+    // votes are counted by user, so voting needs authentication
+    assert!(init_voting_rule(&Authenticator::disabled()).is_err());
+    assert!(
+        init_voting_rule(&Authenticator::from_config(
+            "[[users]]\nname = \"dad\"\ntoken = \"dad-0123456789abcdef\"\n"
+        )?)
+        .is_ok()
+    );
+    Ok(())
+}
+
+#[test]
+fn test_voting_rules() {
+    let [best, good, worst] =
+        ["best", "good", "worst"].map(|name| ReviewScore::from_name(name).unwrap());
+
+    let any_best_wins = VotingRule::AnyWins {
+        score: best,
+        voters: 3,
+    };
+    assert_eq!(any_best_wins.resolve(&[good]), None);
+    assert_eq!(any_best_wins.resolve(&[good, best]), Some(best));
+
+    let majority = VotingRule::Majority { voters: 4 };
+    assert_eq!(majority.resolve(&[good, good]), None);
+    assert_eq!(majority.resolve(&[good, worst, good, good]), Some(good));
+
+    // without agreement the most voted score wins once everyone voted, ties go to the better one
+    let two_of_three = VotingRule::Quorum {
+        votes: 2,
+        voters: 3,
+    };
+    assert_eq!(two_of_three.resolve(&[worst, good]), None);
+    assert_eq!(two_of_three.resolve(&[worst, good, best]), Some(best));
+    assert_eq!(majority.resolve(&[worst, good, worst, good]), Some(good));
+}